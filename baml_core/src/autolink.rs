@@ -0,0 +1,234 @@
+//! Turns bare urls and email addresses in text into `link` commands
+//!
+//! Verbatim commands, the arguments of existing links and other arguments that are not prose
+//! (like image sources, see `Command::prose_start`) are left alone, as are words containing escapes.
+
+use crate::{ASTNode, BasicCommandType, Command};
+
+const URL_PREFIXES: &[&str] = &["http://", "https://", "ftp://", "mailto:"];
+
+fn is_email(s: &str) -> bool {
+    let mut spl = s.splitn(2, '@');
+    let local = spl.next().unwrap();
+    let domain = match spl.next() {
+        Some(x) => x,
+        None => return false,
+    };
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._%+-".contains(c))
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains("..")
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+}
+
+/// The link target for a word, if it is a url or an email address
+fn link_target(word: &str) -> Option<String> {
+    if word.contains('\\') {
+        return None;
+    }
+    if URL_PREFIXES
+        .iter()
+        .any(|p| word.len() > p.len() && word.starts_with(p))
+    {
+        Some(word.to_string())
+    } else if word.len() > 4 && word.starts_with("www.") {
+        Some(format!("http://{}", word))
+    } else if is_email(word) {
+        Some(format!("mailto:{}", word))
+    } else {
+        None
+    }
+}
+
+/// Strips punctuation around a word that most likely isn't part of a url,
+/// returns the byte range of what's left
+fn trim_word(word: &str) -> (usize, usize) {
+    let start = word.len() - word.trim_start_matches(|c| "([<\"'".contains(c)).len();
+    let mut end = word.len();
+    loop {
+        let w = &word[start..end];
+        let last = match w.chars().last() {
+            Some(c) => c,
+            None => break,
+        };
+        // closing parens are kept if they match one in the url, like in wikipedia links
+        let unbalanced_paren = last == ')' && w.matches(')').count() > w.matches('(').count();
+        if ".,;:!?\"'>".contains(last) || unbalanced_paren {
+            end -= last.len_utf8();
+        } else {
+            break;
+        }
+    }
+    (start, end.max(start))
+}
+
+fn link_command(target: String, text: &str) -> ASTNode {
+    let mut arguments = vec![ASTNode::Text(target.clone())];
+    if target != text {
+        arguments.push(ASTNode::ArgSeparator(String::new()));
+        arguments.push(ASTNode::Text(text.to_string()));
+    }
+    ASTNode::CommandCall(Command {
+        backend: None,
+        cmd: BasicCommandType::Link.to_string(),
+        attributes: Vec::new(),
+        arguments,
+        line: None,
+    })
+}
+
+/// Splits the text into text and link nodes
+fn link_text(s: &str) -> Vec<ASTNode> {
+    let mut res = Vec::new();
+    let mut buf = String::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let ws_end = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        buf.push_str(&rest[..ws_end]);
+        rest = &rest[ws_end..];
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..word_end];
+        let (start, end) = trim_word(word);
+        match link_target(&word[start..end]) {
+            Some(target) => {
+                buf.push_str(&word[..start]);
+                if !buf.is_empty() {
+                    res.push(ASTNode::Text(std::mem::take(&mut buf)));
+                }
+                res.push(link_command(target, &word[start..end]));
+                buf.push_str(&word[end..]);
+            }
+            None => buf.push_str(word),
+        }
+        rest = &rest[word_end..];
+    }
+    if !buf.is_empty() || res.is_empty() {
+        res.push(ASTNode::Text(buf));
+    }
+    res
+}
+
+/// Replaces urls and email addresses in all text nodes with links
+pub fn autolink(nodes: &mut Vec<ASTNode>) {
+    let mut res = Vec::with_capacity(nodes.len());
+    for node in std::mem::take(nodes) {
+        match node {
+            ASTNode::Text(s) => res.append(&mut link_text(&s)),
+            ASTNode::CommandCall(mut c) => {
+                let is_link = c.backend.is_none() && c.cmd.parse() == Ok(BasicCommandType::Link);
+                if !is_link && !c.is_verbatim() {
+                    let mut prose = c.arguments.split_off(c.prose_start());
+                    autolink(&mut prose);
+                    c.arguments.append(&mut prose);
+                }
+                res.push(ASTNode::CommandCall(c));
+            }
+            node => res.push(node),
+        }
+    }
+    *nodes = res;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::autolink;
+    use crate::{parse, ASTNode};
+
+    fn links(source: &str) -> Vec<ASTNode> {
+        let mut nodes = parse(source.to_string()).unwrap().nodes;
+        autolink(&mut nodes);
+        nodes
+    }
+
+    fn text(s: &str) -> ASTNode {
+        ASTNode::Text(s.to_string())
+    }
+
+    /// The arguments of the link commands, as plain text separated by `|`
+    fn targets(nodes: &[ASTNode]) -> Vec<String> {
+        let mut res = Vec::new();
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if c.cmd == "link" {
+                    res.push(
+                        c.arguments
+                            .iter()
+                            .map(|n| match n {
+                                ASTNode::ArgSeparator(_) => "|".to_string(),
+                                n => n.plain_text(),
+                            })
+                            .collect(),
+                    );
+                } else {
+                    res.append(&mut targets(&c.arguments));
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn urls() {
+        let nodes = links("see https://example.com or www.example.org now");
+        assert_eq!(nodes[0], text("see "));
+        assert_eq!(nodes[2], text(" or "));
+        assert_eq!(nodes[4], text(" now"));
+        assert_eq!(
+            targets(&nodes),
+            vec![
+                "https://example.com",
+                "http://www.example.org|www.example.org"
+            ]
+        );
+        assert_eq!(
+            targets(&links("ftp:// and www. and http")),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn emails() {
+        assert_eq!(
+            targets(&links("mail a.b+c@example.com, not a@b or @example.com")),
+            vec!["mailto:a.b+c@example.com|a.b+c@example.com"]
+        );
+    }
+
+    #[test]
+    fn trailing_punctuation() {
+        let nodes = links("(see https://en.wikipedia.org/wiki/Rust_(language)), https://a.com.");
+        assert_eq!(
+            targets(&nodes),
+            vec![
+                "https://en.wikipedia.org/wiki/Rust_(language)",
+                "https://a.com"
+            ]
+        );
+        assert_eq!(nodes[0], text("(see "));
+        assert_eq!(nodes.last(), Some(&text(".")));
+    }
+
+    #[test]
+    fn skipped_arguments() {
+        let nodes = links(
+            "[link https://a.com; https://b.com] [code https://c.com] https\\://d.com \
+             [img www.example.com/a.png; www.alt.com] [cite www.key.com] [b www.e.com]",
+        );
+        assert_eq!(
+            targets(&nodes),
+            vec![
+                "https://a.com|https://b.com",
+                "http://www.alt.com|www.alt.com",
+                "http://www.e.com|www.e.com"
+            ]
+        );
+    }
+}
@@ -0,0 +1,472 @@
+//! Citations and reference lists
+//!
+//! A document names its BibTeX file with `!bibliography refs.bib` (relative to the document)
+//! and cites entries with `[cite key]` or `[cite key1, key2]`, optionally with `{page = 12}`.
+//! `!citation-style` selects `numeric` (`[1]`, the default) or `author-year` (`(Knuth 1984)`).
+//! Citations become links to the entries of the reference list, which only contains cited entries.
+
+use crate::bibtex::clean_value;
+use crate::bibtex::{parse_bibtex, BibEntry, BibError};
+use crate::{split_arguments, ASTNode, BasicCommandType, Command};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum CitationStyle {
+    #[default]
+    Numeric,
+    AuthorYear,
+}
+
+impl FromStr for CitationStyle {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "numeric" => CitationStyle::Numeric,
+            "author-year" => CitationStyle::AuthorYear,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl ToString for CitationStyle {
+    fn to_string(&self) -> String {
+        match self {
+            CitationStyle::Numeric => "numeric",
+            CitationStyle::AuthorYear => "author-year",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Bibliography {
+    pub entries: Vec<BibEntry>,
+}
+
+impl Bibliography {
+    pub fn parse(s: &str) -> Result<Self, BibError> {
+        Ok(Self {
+            entries: parse_bibtex(s)?,
+        })
+    }
+
+    /// Reads and parses a BibTeX file, the error includes the path
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("can't read {} ({:?})", path.to_string_lossy(), e))?;
+        Self::parse(&s).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+    }
+
+    /// Loads the `!bibliography` of a document, `dir` is the directory of the document
+    pub fn from_metadata(
+        meta: &HashMap<String, String>,
+        dir: &Path,
+    ) -> Result<Option<Self>, String> {
+        match meta.get("bibliography") {
+            Some(p) => Self::load(&dir.join(p.trim())).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BibEntry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+}
+
+/// The id of the reference list entry for a key
+pub fn entry_id(key: &str) -> String {
+    format!("bib-{}", key)
+}
+
+/// The byte index of the first (or last) `c` outside of braces
+fn find_unbraced(s: &str, c: char, last: bool) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut res = None;
+    for (i, ch) in s.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ if ch == c && depth == 0 => {
+                res = Some(i);
+                if !last {
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// The names of the authors (or editors) as `(first names, last name)`
+///
+/// Braced parts like `{Barnes and Noble}` are never split.
+fn names(entry: &BibEntry) -> Vec<(String, String)> {
+    let names = match entry.names("author").or_else(|| entry.names("editor")) {
+        Some(names) => names,
+        None => return Vec::new(),
+    };
+    names
+        .iter()
+        .map(|name| {
+            let name = name.trim();
+            let (first, last) = match find_unbraced(name, ',', false) {
+                Some(i) => (&name[i + 1..], &name[..i]),
+                None => match find_unbraced(name, ' ', true) {
+                    Some(i) => (&name[..i], &name[i + 1..]),
+                    None => ("", name),
+                },
+            };
+            (clean_value(first), clean_value(last))
+        })
+        .collect()
+}
+
+fn join_names(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [a] => a.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
+
+fn year(entry: &BibEntry) -> &str {
+    entry.field("year").unwrap_or("n.d.")
+}
+
+/// `Knuth 1984`, `Knuth and Lamport 1990` or `Knuth et al. 1990`
+fn author_year_label(entry: &BibEntry) -> String {
+    let last = names(entry).into_iter().map(|(_, l)| l).collect::<Vec<_>>();
+    let authors = match &last[..] {
+        [] => entry.field("title").unwrap_or(&entry.key).to_string(),
+        [a] => a.clone(),
+        [a, b] => format!("{} and {}", a, b),
+        [a, ..] => format!("{} et al.", a),
+    };
+    format!("{} {}", authors, year(entry))
+}
+
+fn italic(s: &str) -> ASTNode {
+    ASTNode::CommandCall(Command {
+        backend: None,
+        cmd: BasicCommandType::Italic.to_string(),
+        attributes: Vec::new(),
+        arguments: vec![ASTNode::Text(s.to_string())],
+        line: None,
+    })
+}
+
+fn link(target: String, text: Vec<ASTNode>) -> ASTNode {
+    let mut arguments = vec![ASTNode::Text(target), ASTNode::ArgSeparator(String::new())];
+    arguments.extend(text);
+    ASTNode::CommandCall(Command {
+        backend: None,
+        cmd: BasicCommandType::Link.to_string(),
+        attributes: Vec::new(),
+        arguments,
+        line: None,
+    })
+}
+
+/// Formats an entry of the reference list
+fn format_entry(entry: &BibEntry, style: CitationStyle) -> Vec<ASTNode> {
+    let mut res = Vec::new();
+    let text = |res: &mut Vec<ASTNode>, s: String| res.push(ASTNode::Text(s));
+    let authors = join_names(
+        &names(entry)
+            .into_iter()
+            .map(|(first, last)| format!("{} {}", first, last).trim().to_string())
+            .collect::<Vec<_>>(),
+    );
+    match style {
+        CitationStyle::Numeric if !authors.is_empty() => text(&mut res, format!("{}. ", authors)),
+        CitationStyle::Numeric => (),
+        CitationStyle::AuthorYear => {
+            let authors = if authors.is_empty() {
+                entry.field("title").unwrap_or(&entry.key).to_string()
+            } else {
+                authors
+            };
+            text(&mut res, format!("{} ({}). ", authors, year(entry)))
+        }
+    }
+    // books are in italics themselves, everything else is in the venue in italics
+    let is_book = entry.kind == "book";
+    if let Some(title) = entry.field("title") {
+        if is_book {
+            res.push(italic(title));
+            text(&mut res, ". ".to_string());
+        } else {
+            text(&mut res, format!("{}. ", title));
+        }
+    }
+    let mut details = Vec::new();
+    if let Some(venue) = entry.field("journal").or_else(|| entry.field("booktitle")) {
+        res.push(italic(venue));
+        match (entry.field("volume"), entry.field("number")) {
+            (Some(v), Some(n)) => details.push(format!("{}({})", v, n)),
+            (Some(v), None) => details.push(v.to_string()),
+            _ => (),
+        }
+    }
+    if let Some(pages) = entry.field("pages") {
+        details.push(format!("pp. {}", pages));
+    }
+    if let Some(publisher) = entry
+        .field("publisher")
+        .or_else(|| entry.field("institution"))
+    {
+        details.push(publisher.to_string());
+    }
+    if style == CitationStyle::Numeric {
+        if let Some(year) = entry.field("year") {
+            details.push(year.to_string());
+        }
+    }
+    let starts_with_venue = matches!(res.last(), Some(ASTNode::CommandCall(_))) && !is_book;
+    if !details.is_empty() {
+        let sep = if starts_with_venue { ", " } else { "" };
+        text(&mut res, format!("{}{}. ", sep, details.join(", ")));
+    } else if starts_with_venue {
+        text(&mut res, ". ".to_string());
+    }
+    if let Some(url) = entry.field("url").map(str::to_string).or_else(|| {
+        entry
+            .field("doi")
+            .map(|doi| format!("https://doi.org/{}", doi))
+    }) {
+        res.push(link(url.clone(), vec![ASTNode::Text(url)]));
+    }
+    if let Some(ASTNode::Text(s)) = res.last_mut() {
+        *s = s.trim_end().to_string();
+    }
+    res
+}
+
+fn is_cite(cmd: &Command) -> bool {
+    cmd.backend.is_none() && cmd.cmd.parse() == Ok(BasicCommandType::Cite)
+}
+
+/// An entry of the reference list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub id: String,
+    /// The number for numeric citations
+    pub number: Option<String>,
+    pub content: Vec<ASTNode>,
+}
+
+/// The cited entries of a document, in the order of their first citation
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Citations {
+    pub style: CitationStyle,
+    pub keys: Vec<String>,
+}
+
+impl Citations {
+    /// Replaces the arguments of every `[cite]` with the in-text marker, returns warnings for unknown keys
+    ///
+    /// The markers link to the reference list entries, unknown keys are shown as `key?`
+    pub fn resolve(
+        nodes: &mut [ASTNode],
+        bib: &Bibliography,
+        style: CitationStyle,
+    ) -> (Self, Vec<String>) {
+        let mut res = Self {
+            style,
+            keys: Vec::new(),
+        };
+        let mut warnings = Vec::new();
+        res.resolve_inner(nodes, bib, &mut warnings);
+        (res, warnings)
+    }
+
+    fn resolve_inner(
+        &mut self,
+        nodes: &mut [ASTNode],
+        bib: &Bibliography,
+        warnings: &mut Vec<String>,
+    ) {
+        for node in nodes {
+            let c = match node {
+                ASTNode::CommandCall(c) => c,
+                _ => continue,
+            };
+            if !is_cite(c) {
+                self.resolve_inner(&mut c.arguments, bib, warnings);
+                continue;
+            }
+            let keys = split_arguments(std::mem::take(&mut c.arguments))
+                .iter()
+                .flat_map(|arg| {
+                    arg.iter()
+                        .map(ASTNode::plain_text)
+                        .collect::<String>()
+                        .split(',')
+                        .map(|k| k.trim().to_string())
+                        .filter(|k| !k.is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let page = c
+                .attributes
+                .iter()
+                .position(|(k, _)| k == "page")
+                .map(|i| c.attributes.remove(i).1);
+            let (open, sep, close) = match self.style {
+                CitationStyle::Numeric => ("[", ", ", "]"),
+                CitationStyle::AuthorYear => ("(", "; ", ")"),
+            };
+            let mut arguments = vec![ASTNode::Text(open.to_string())];
+            for (i, key) in keys.iter().enumerate() {
+                if i > 0 {
+                    arguments.push(ASTNode::Text(sep.to_string()));
+                }
+                let entry = match bib.get(key) {
+                    Some(e) => e,
+                    None => {
+                        warnings.push(format!("citation of unknown key `{}`", key));
+                        arguments.push(ASTNode::Text(format!("{}?", key)));
+                        continue;
+                    }
+                };
+                if !self.keys.contains(key) {
+                    self.keys.push(key.clone());
+                }
+                let label = match self.style {
+                    CitationStyle::Numeric => {
+                        (self.keys.iter().position(|k| k == key).unwrap() + 1).to_string()
+                    }
+                    CitationStyle::AuthorYear => author_year_label(entry),
+                };
+                arguments.push(link(
+                    format!("#{}", entry_id(key)),
+                    vec![ASTNode::Text(label)],
+                ));
+            }
+            if let Some(page) = page {
+                arguments.push(ASTNode::Text(format!(", p. {}", page.trim())));
+            }
+            arguments.push(ASTNode::Text(close.to_string()));
+            c.arguments = arguments;
+        }
+    }
+
+    /// The formatted reference list
+    ///
+    /// Numeric lists are in citation order, author-year lists are sorted by author and year.
+    pub fn reference_list(&self, bib: &Bibliography) -> Vec<Reference> {
+        let mut entries = self
+            .keys
+            .iter()
+            .filter_map(|k| bib.get(k))
+            .enumerate()
+            .collect::<Vec<_>>();
+        if self.style == CitationStyle::AuthorYear {
+            entries.sort_by_key(|(_, e)| {
+                let last = names(e)
+                    .into_iter()
+                    .map(|(_, l)| l.to_lowercase())
+                    .collect::<Vec<_>>();
+                (last, year(e).to_string())
+            });
+        }
+        entries
+            .into_iter()
+            .map(|(i, e)| {
+                let number = match self.style {
+                    CitationStyle::Numeric => Some((i + 1).to_string()),
+                    CitationStyle::AuthorYear => None,
+                };
+                Reference {
+                    id: entry_id(&e.key),
+                    number,
+                    content: format_entry(e, self.style),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bibliography, CitationStyle, Citations};
+    use crate::{parse, ASTNode};
+
+    const BIB: &str =
+        "@book{knuth, author = {Donald E. Knuth}, title = {The TeXbook}, year = 1984}\n\
+        @article{lamport, author = {Lamport, Leslie and {Barnes and Noble}}, title = {LaTeX},\n\
+        journal = {TUGboat}, volume = 1, year = 1986}";
+
+    fn cite(source: &str, style: CitationStyle) -> (Vec<ASTNode>, Citations, Vec<String>) {
+        let bib = Bibliography::parse(BIB).unwrap();
+        let mut nodes = parse(source.to_string()).unwrap().nodes;
+        let (citations, warnings) = Citations::resolve(&mut nodes, &bib, style);
+        (nodes, citations, warnings)
+    }
+
+    /// The text of the nodes, without link targets
+    fn text(nodes: &[ASTNode]) -> String {
+        nodes
+            .iter()
+            .map(|node| match node {
+                ASTNode::CommandCall(c) if c.cmd == "link" => {
+                    let sep = c
+                        .arguments
+                        .iter()
+                        .position(|n| matches!(n, ASTNode::ArgSeparator(_)));
+                    text(&c.arguments[sep.map_or(0, |i| i + 1)..])
+                }
+                ASTNode::CommandCall(c) => text(&c.arguments),
+                node => node.plain_text(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numeric() {
+        let (nodes, citations, warnings) = cite(
+            "[cite lamport] [i [cite knuth, lamport]]",
+            CitationStyle::Numeric,
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(citations.keys, ["lamport", "knuth"]);
+        assert_eq!(text(&nodes), "[1] [2, 1]");
+        let list = citations.reference_list(&Bibliography::parse(BIB).unwrap());
+        assert_eq!(list[0].id, "bib-lamport");
+        assert_eq!(list[1].number.as_deref(), Some("2"));
+        assert_eq!(
+            list[1]
+                .content
+                .iter()
+                .map(ASTNode::plain_text)
+                .collect::<String>(),
+            "Donald E. Knuth. The TeXbook. 1984."
+        );
+    }
+
+    #[test]
+    fn author_year() {
+        let (nodes, citations, _) =
+            cite("[cite{page = 3} lamport; knuth]", CitationStyle::AuthorYear);
+        assert_eq!(
+            text(&nodes),
+            "(Lamport and Barnes and Noble 1986; Knuth 1984, p. 3)"
+        );
+        let list = citations.reference_list(&Bibliography::parse(BIB).unwrap());
+        let ids = list.iter().map(|r| r.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["bib-knuth", "bib-lamport"]);
+        assert!(list.iter().all(|r| r.number.is_none()));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let (nodes, citations, warnings) = cite("[cite knuth, nobody]", CitationStyle::Numeric);
+        assert_eq!(warnings, ["citation of unknown key `nobody`"]);
+        assert_eq!(citations.keys, ["knuth"]);
+        assert_eq!(text(&nodes), "[1, nobody?]");
+    }
+}
@@ -0,0 +1,360 @@
+//! A parser for the parts of BibTeX that bibliographies need
+//!
+//! Entries (`@article{key, author = {...}, year = 1984}`) and `@string` abbreviations are read,
+//! `@comment` and `@preamble` are skipped. Braces used for protecting case are removed from values
+//! and a few common escapes (`\&`, `--`, `~`) are replaced.
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BibEntry {
+    /// The lowercase entry type, e.g. `article`
+    pub kind: String,
+    pub key: String,
+    /// Fields by their lowercase name
+    pub fields: HashMap<String, String>,
+    /// The names in the name fields (`author` and `editor`), split before the braces are removed
+    /// and still with them, so that `{Barnes and Noble}` is one name
+    pub names: HashMap<String, Vec<String>>,
+}
+
+/// Fields that are lists of names separated by `and`
+const NAME_FIELDS: &[&str] = &["author", "editor"];
+
+impl BibEntry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    /// The names in a name field, see `names`
+    pub fn names(&self, field: &str) -> Option<&[String]> {
+        self.names.get(field).map(Vec::as_slice)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BibError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for BibError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for BibError {}
+
+const MONTHS: &[(&str, &str)] = &[
+    ("jan", "January"),
+    ("feb", "February"),
+    ("mar", "March"),
+    ("apr", "April"),
+    ("may", "May"),
+    ("jun", "June"),
+    ("jul", "July"),
+    ("aug", "August"),
+    ("sep", "September"),
+    ("oct", "October"),
+    ("nov", "November"),
+    ("dec", "December"),
+];
+
+/// Removes protecting braces, replaces escapes and collapses whitespace
+pub(crate) fn clean_value(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) if "&%$#_{}".contains(c) => res.push(c),
+                // commands like `\LaTeX` are reduced to their name
+                Some(c) if c.is_alphabetic() => res.push(c),
+                Some(c) => {
+                    res.push('\\');
+                    res.push(c);
+                }
+                None => res.push('\\'),
+            },
+            '{' | '}' => (),
+            '~' => res.push('\u{a0}'),
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    res.push('—');
+                } else {
+                    res.push('–');
+                }
+            }
+            c => res.push(c),
+        }
+    }
+    res.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Splits a list of names at the `and`s outside of braces
+fn split_names(s: &str) -> Vec<String> {
+    let mut res = vec![Vec::new()];
+    let mut depth = 0usize;
+    for word in s.split_whitespace() {
+        if word == "and" && depth == 0 {
+            res.push(Vec::new());
+            continue;
+        }
+        for c in word.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+        }
+        res.last_mut().unwrap().push(word);
+    }
+    res.into_iter()
+        .filter(|words| !words.is_empty())
+        .map(|words| words.join(" "))
+        .collect()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    strings: HashMap<String, String>,
+}
+
+impl Parser {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, BibError> {
+        Err(BibError {
+            line: self.chars[..self.pos.min(self.chars.len())]
+                .iter()
+                .filter(|c| **c == '\n')
+                .count()
+                + 1,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), BibError> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return self.error(format!("expected `{}`", c));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<String, BibError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !"{}()=,#\"".contains(c))
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return self.error("expected a name");
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Reads up to the matching closing delimiter, the opening one has already been read
+    fn balanced(&mut self, close: char) -> Result<String, BibError> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return self.error(format!("missing `{}`", close)),
+                Some('\\') => self.pos += 1,
+                Some('{') => depth += 1,
+                Some(c) if c == close && depth == 0 => break,
+                Some('}') => depth -= 1,
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        let res = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        Ok(res)
+    }
+
+    /// Reads a value, which may consist of several parts joined with `#`
+    fn value(&mut self) -> Result<String, BibError> {
+        let mut res = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    res.push_str(&self.balanced('}')?);
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    res.push_str(&self.balanced('"')?);
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        res.push(self.peek().unwrap());
+                        self.pos += 1;
+                    }
+                }
+                _ => {
+                    let name = self.identifier()?.to_lowercase();
+                    match self.strings.get(&name) {
+                        Some(s) => res.push_str(s),
+                        None => match MONTHS.iter().find(|(k, _)| *k == name) {
+                            Some((_, month)) => res.push_str(month),
+                            None => return self.error(format!("undefined string `{}`", name)),
+                        },
+                    }
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                return Ok(res);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn entry(&mut self) -> Result<Option<BibEntry>, BibError> {
+        let kind = self.identifier()?.to_lowercase();
+        self.skip_whitespace();
+        let close = match self.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => return self.error("expected `{` or `(`"),
+        };
+        self.pos += 1;
+        match kind.as_str() {
+            "comment" | "preamble" => {
+                self.balanced(close)?;
+                return Ok(None);
+            }
+            "string" => {
+                let name = self.identifier()?.to_lowercase();
+                self.expect('=')?;
+                let value = self.value()?;
+                self.strings.insert(name, value);
+                self.expect(close)?;
+                return Ok(None);
+            }
+            _ => (),
+        }
+        let key = self.identifier()?;
+        let mut fields = HashMap::new();
+        let mut names = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return self.error(format!("expected `,` or `{}`", close)),
+            }
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                self.pos += 1;
+                break;
+            }
+            let name = self.identifier()?.to_lowercase();
+            self.expect('=')?;
+            let value = self.value()?;
+            if NAME_FIELDS.contains(&name.as_str()) {
+                names.insert(name.clone(), split_names(&value));
+            }
+            fields.insert(name, clean_value(&value));
+        }
+        Ok(Some(BibEntry {
+            kind,
+            key,
+            fields,
+            names,
+        }))
+    }
+}
+
+/// Parses the entries of a BibTeX file, in order
+pub fn parse_bibtex(s: &str) -> Result<Vec<BibEntry>, BibError> {
+    let mut p = Parser {
+        chars: s.chars().collect(),
+        pos: 0,
+        strings: HashMap::new(),
+    };
+    let mut res = Vec::new();
+    // everything outside of entries is a comment
+    while let Some(i) = p.chars[p.pos..].iter().position(|c| *c == '@') {
+        p.pos += i + 1;
+        if let Some(entry) = p.entry()? {
+            res.push(entry);
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_bibtex;
+
+    #[test]
+    fn fields() {
+        let entries = parse_bibtex(
+            "% a comment\n@string{tug = \"TeX Users \" # {Group}}\n\
+             @Book{knuth84,\n  Title = {The {\\TeX}book},\n  publisher = tug,\n  \
+             month = mar, year = 1984, pages = \"1--10\",\n}\n\
+             @comment{ignored}\n@misc(b, note = {a {\"quoted\"} note})",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        let knuth = &entries[0];
+        assert_eq!(
+            (knuth.kind.as_str(), knuth.key.as_str()),
+            ("book", "knuth84")
+        );
+        assert_eq!(knuth.field("title"), Some("The TeXbook"));
+        assert_eq!(knuth.field("publisher"), Some("TeX Users Group"));
+        assert_eq!(knuth.field("month"), Some("March"));
+        assert_eq!(knuth.field("year"), Some("1984"));
+        assert_eq!(knuth.field("pages"), Some("1–10"));
+        assert_eq!(entries[1].field("note"), Some("a \"quoted\" note"));
+    }
+
+    #[test]
+    fn names() {
+        let entries =
+            parse_bibtex("@book{b, author = {{Barnes and Noble} and Knuth, Donald}}").unwrap();
+        assert_eq!(
+            entries[0].names("author").unwrap(),
+            ["{Barnes and Noble}", "Knuth, Donald"]
+        );
+        assert_eq!(
+            entries[0].field("author"),
+            Some("Barnes and Noble and Knuth, Donald")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = parse_bibtex("@article{\n, title = {x}}").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: expected a name");
+        let err = parse_bibtex("@article{a,\n title = {x}\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        let err = parse_bibtex("@article{a, publisher = nobody}").unwrap_err();
+        assert_eq!(err.message, "undefined string `nobody`");
+    }
+}
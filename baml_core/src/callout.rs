@@ -0,0 +1,140 @@
+//! Callouts (admonitions) like notes and warnings
+//!
+//! `[note ...]`, `[tip ...]`, `[important ...]`, `[warning ...]` and `[caution ...]` are shorthands for
+//! `[callout{kind = ...} ...]`. The optional `title` attribute replaces the default title (the kind).
+
+use crate::values::{parse_value, ValueError};
+use crate::Command;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum CalloutKind {
+    #[default]
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl FromStr for CalloutKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::CalloutKind::*;
+        Ok(match s {
+            "note" => Note,
+            "tip" => Tip,
+            "important" => Important,
+            "warning" => Warning,
+            "caution" => Caution,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl ToString for CalloutKind {
+    fn to_string(&self) -> String {
+        use self::CalloutKind::*;
+        match self {
+            Note => "note",
+            Tip => "tip",
+            Important => "important",
+            Warning => "warning",
+            Caution => "caution",
+        }
+        .to_string()
+    }
+}
+
+impl CalloutKind {
+    /// The title used when a callout doesn't have one
+    pub fn default_title(self) -> &'static str {
+        use self::CalloutKind::*;
+        match self {
+            Note => "Note",
+            Tip => "Tip",
+            Important => "Important",
+            Warning => "Warning",
+            Caution => "Caution",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Callout {
+    pub kind: CalloutKind,
+    pub title: Option<String>,
+}
+
+impl Callout {
+    /// Reads the kind and title (the `title` attribute) of a callout command and removes those attributes
+    ///
+    /// The `kind` attribute takes precedence over the command name
+    pub fn extract(cmd: &mut Command) -> Result<Self, ValueError> {
+        let mut kind = cmd.cmd.parse().unwrap_or_default();
+        let mut title = None;
+        for (k, v) in &cmd.attributes {
+            match k.as_str() {
+                "kind" => {
+                    kind = parse_value(
+                        &cmd.cmd,
+                        k,
+                        v.trim(),
+                        "`note`, `tip`, `important`, `warning` or `caution`",
+                    )
+                    .map_err(|e| e.at_line(cmd.line))?
+                }
+                "title" => title = Some(v.trim().to_string()),
+                _ => (),
+            }
+        }
+        cmd.attributes.retain(|(k, _)| k != "kind" && k != "title");
+        Ok(Self { kind, title })
+    }
+
+    pub fn title(&self) -> &str {
+        self.title
+            .as_deref()
+            .unwrap_or_else(|| self.kind.default_title())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Callout, CalloutKind};
+    use crate::{parse, ASTNode, Command};
+
+    fn command(source: &str) -> Command {
+        match parse(source.to_string()).unwrap().nodes.remove(0) {
+            ASTNode::CommandCall(c) => c,
+            node => panic!("expected a command, got {:?}", node),
+        }
+    }
+
+    #[test]
+    fn kinds_and_titles() {
+        let mut cmd = command("[warning{title = Careful; class = x} a]");
+        let callout = Callout::extract(&mut cmd).unwrap();
+        assert_eq!(callout.kind, CalloutKind::Warning);
+        assert_eq!(callout.title(), "Careful");
+        assert_eq!(cmd.attributes, vec![("class".into(), "x".into())]);
+
+        let mut cmd = command("[callout{kind = tip} a]");
+        let callout = Callout::extract(&mut cmd).unwrap();
+        assert_eq!(callout.kind, CalloutKind::Tip);
+        assert_eq!(callout.title(), "Tip");
+
+        let mut cmd = command("[note{kind = caution} a]");
+        assert_eq!(
+            Callout::extract(&mut cmd).unwrap().kind,
+            CalloutKind::Caution
+        );
+    }
+
+    #[test]
+    fn invalid_kind() {
+        let mut cmd = command("[callout{kind = danger} a]");
+        assert!(Callout::extract(&mut cmd).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Figures with captions
+//!
+//! `[fig <content>; <caption>]` wraps an image (or anything else) and gives it a caption.
+//! Figures are numbered in document order unless they are `{numbered = no}`,
+//! and can be labeled like sections (`[fig [img ...][label arch]; Architecture]`).
+
+use crate::numbering::number_sequentially;
+use crate::{repeatable_nodes, split_first_argument, ASTNode, BasicCommandType, Command};
+
+pub fn is_figure(cmd: &Command) -> bool {
+    cmd.backend.is_none() && cmd.cmd.parse() == Ok(BasicCommandType::Figure)
+}
+
+/// Sets the `number` attribute of all figures, and an `id` if they don't have one, returns how many there are
+pub fn number_figures(nodes: &mut [ASTNode]) -> usize {
+    number_sequentially(nodes, is_figure, "figure")
+}
+
+/// A numbered figure, for lists of figures
+#[derive(Debug, Clone, PartialEq)]
+pub struct FigureEntry {
+    pub number: String,
+    pub id: Option<String>,
+    /// A copy of the caption without footnotes, index markers and ids (see `repeatable_nodes`)
+    pub caption: Vec<ASTNode>,
+}
+
+fn list_inner(nodes: &[ASTNode], res: &mut Vec<FigureEntry>) {
+    for node in nodes {
+        if let ASTNode::CommandCall(c) = node {
+            if is_figure(c) {
+                if let Some(number) = c.attribute("number") {
+                    res.push(FigureEntry {
+                        number: number.to_string(),
+                        id: c.attribute("id").map(str::to_string),
+                        caption: repeatable_nodes(&split_first_argument(c.arguments.clone()).1),
+                    });
+                }
+            }
+            list_inner(&c.arguments, res);
+        }
+    }
+}
+
+/// The numbered figures in the nodes, in document order
+pub fn list_of_figures(nodes: &[ASTNode]) -> Vec<FigureEntry> {
+    let mut res = Vec::new();
+    list_inner(nodes, &mut res);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list_of_figures, number_figures};
+    use crate::{parse, ASTNode};
+
+    #[test]
+    fn numbering() {
+        let mut nodes = parse(
+            "[fig{id = arch} [img a.png]; Arch[fn x]][fig{numbered = no} [img b.png]; B]\
+             [div [fig [img c.png]; [i C] [index c]]][figure [img x.png]][fig [img d.png]]"
+                .to_string(),
+        )
+        .unwrap()
+        .nodes;
+        assert_eq!(number_figures(&mut nodes), 3);
+        let figures = list_of_figures(&nodes);
+        let entries = figures
+            .iter()
+            .map(|f| {
+                (
+                    f.number.as_str(),
+                    f.id.as_deref(),
+                    f.caption
+                        .iter()
+                        .map(ASTNode::plain_text)
+                        .collect::<String>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("1", Some("arch"), "Arch".to_string()),
+                ("2", Some("figure-2"), "C ".to_string()),
+                ("3", Some("figure-3"), String::new()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Footnotes
+//!
+//! `[footnote text]` (or `[fn text]`) stays where it is written, so that backends with native footnotes
+//! can use them. Every footnote gets its number as the `number` attribute, in document order.
+//! Backends that collect the notes themselves (e.g. at the end of the document) can use `Footnote`
+//! for single notes or `collect_footnotes` for all notes of a document.
+
+use crate::{ASTNode, BasicCommandType, Command};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    pub number: usize,
+    /// The text of the note, footnotes in it are still `[footnote]` commands
+    pub content: Vec<ASTNode>,
+}
+
+impl Footnote {
+    /// Reads a numbered footnote command, `None` for other commands
+    pub fn from_command(cmd: &Command) -> Option<Self> {
+        if !is_footnote(cmd) {
+            return None;
+        }
+        Some(Self {
+            number: cmd.attribute("number")?.parse().ok()?,
+            content: cmd.arguments.clone(),
+        })
+    }
+}
+
+pub fn is_footnote(cmd: &Command) -> bool {
+    cmd.backend.is_none() && cmd.cmd.parse() == Ok(BasicCommandType::Footnote)
+}
+
+fn number_inner(nodes: &mut [ASTNode], count: &mut usize) {
+    for node in nodes {
+        if let ASTNode::CommandCall(c) = node {
+            if is_footnote(c) {
+                *count += 1;
+                c.attributes.retain(|(k, _)| k != "number");
+                c.attributes.push(("number".to_string(), count.to_string()));
+            }
+            number_inner(&mut c.arguments, count);
+        }
+    }
+}
+
+/// Numbers the footnotes, including footnotes in footnotes, and returns how many there are
+pub fn number_footnotes(nodes: &mut [ASTNode]) -> usize {
+    let mut count = 0;
+    number_inner(nodes, &mut count);
+    count
+}
+
+fn collect_inner(nodes: &[ASTNode], res: &mut Vec<Footnote>) {
+    for node in nodes {
+        if let ASTNode::CommandCall(c) = node {
+            res.extend(Footnote::from_command(c));
+            collect_inner(&c.arguments, res);
+        }
+    }
+}
+
+/// The numbered footnotes, including footnotes in footnotes, ordered by their numbers
+pub fn collect_footnotes(nodes: &[ASTNode]) -> Vec<Footnote> {
+    let mut res = Vec::new();
+    collect_inner(nodes, &mut res);
+    res.sort_by_key(|note| note.number);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_footnotes, number_footnotes};
+    use crate::{parse, ASTNode};
+
+    #[test]
+    fn nested_footnotes() {
+        let mut nodes = parse("a[fn b[fn c]] d[fn e]".to_string()).unwrap().nodes;
+        assert_eq!(number_footnotes(&mut nodes), 3);
+        let notes = collect_footnotes(&nodes);
+        let text = |n: usize| {
+            notes[n]
+                .content
+                .iter()
+                .map(ASTNode::plain_text)
+                .collect::<String>()
+        };
+        assert_eq!(
+            notes.iter().map(|n| n.number).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            (text(0), text(1), text(2)),
+            ("bc".into(), "c".into(), "e".into())
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Glossary terms and abbreviations
+//!
+//! `[define <term>; <definition>]` defines a term anywhere in the document (and is removed).
+//! Terms without lowercase letters, like `API`, are abbreviations unless they are `{abbr = no}`
+//! (and `{abbr = yes}` makes any term one). Every use of an abbreviation becomes
+//! `[abbr{title = <definition>} <term>]`, and if the document has a `[glossary]`,
+//! the first use of every term links to its entry there.
+
+use crate::slug::{is_heading, slugify};
+use crate::{split_arguments, trim_nodes, ASTNode, BasicCommandType, Command};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlossaryEntry {
+    pub term: String,
+    /// The id of the entry in the glossary
+    pub id: String,
+    pub definition: Vec<ASTNode>,
+    pub abbreviation: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Glossary {
+    /// The entries in the order of their definitions
+    pub entries: Vec<GlossaryEntry>,
+}
+
+fn is_command(node: &ASTNode, name: &str) -> bool {
+    matches!(node, ASTNode::CommandCall(c) if c.backend.is_none() && c.cmd == name)
+}
+
+fn command(cmd: &str, attributes: Vec<(String, String)>, arguments: Vec<ASTNode>) -> ASTNode {
+    ASTNode::CommandCall(Command {
+        backend: None,
+        cmd: cmd.to_string(),
+        attributes,
+        arguments,
+        line: None,
+    })
+}
+
+fn is_word_boundary(c: Option<char>) -> bool {
+    !c.is_some_and(char::is_alphanumeric)
+}
+
+/// Whether the nodes contain a `[glossary]`
+fn has_glossary(nodes: &[ASTNode]) -> bool {
+    nodes.iter().any(|node| match node {
+        ASTNode::CommandCall(c) => {
+            (c.backend.is_none() && c.cmd.parse() == Ok(BasicCommandType::Glossary))
+                || has_glossary(&c.arguments)
+        }
+        _ => false,
+    })
+}
+
+impl Glossary {
+    /// Collects and removes all `[define]` commands, returns warnings for malformed or repeated ones
+    pub fn collect(nodes: &mut Vec<ASTNode>) -> (Self, Vec<String>) {
+        let mut res = Self::default();
+        let mut warnings = Vec::new();
+        res.collect_inner(nodes, &mut warnings);
+        (res, warnings)
+    }
+
+    fn collect_inner(&mut self, nodes: &mut Vec<ASTNode>, warnings: &mut Vec<String>) {
+        let mut definitions = Vec::new();
+        nodes.retain(|node| {
+            if is_command(node, "define") {
+                definitions.push(node.clone());
+                return false;
+            }
+            true
+        });
+        for node in definitions {
+            if let ASTNode::CommandCall(c) = node {
+                self.add(c, warnings);
+            }
+        }
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                self.collect_inner(&mut c.arguments, warnings);
+            }
+        }
+    }
+
+    fn add(&mut self, cmd: Command, warnings: &mut Vec<String>) {
+        let abbr = cmd.flag("abbr");
+        let mut args = split_arguments(cmd.arguments).into_iter();
+        let term = args
+            .next()
+            .unwrap_or_default()
+            .iter()
+            .map(ASTNode::plain_text)
+            .collect::<String>()
+            .trim()
+            .to_string();
+        let definition = args.next().unwrap_or_default();
+        if term.is_empty() || definition.is_empty() {
+            warnings.push(format!("`[define {}]` needs a term and a definition", term));
+            return;
+        }
+        if self.get(&term).is_some() {
+            warnings.push(format!("`{}` is defined more than once", term));
+            return;
+        }
+        let abbreviation = abbr.unwrap_or_else(|| {
+            !term.chars().any(char::is_lowercase)
+                && term.chars().filter(|c| c.is_uppercase()).count() >= 2
+        });
+        self.entries.push(GlossaryEntry {
+            id: format!("gloss-{}", slugify(&term)),
+            term,
+            definition: trim_nodes(definition),
+            abbreviation,
+        });
+    }
+
+    pub fn get(&self, term: &str) -> Option<&GlossaryEntry> {
+        self.entries.iter().find(|e| e.term == term)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries sorted by term, ignoring case
+    pub fn sorted(&self) -> Vec<&GlossaryEntry> {
+        let mut res = self.entries.iter().collect::<Vec<_>>();
+        res.sort_by_key(|e| (e.term.to_lowercase(), e.term.clone()));
+        res
+    }
+
+    /// The first use of a term in `s` at or after `start`, as `(byte index, entry)`
+    fn find_term(&self, s: &str, start: usize) -> Option<(usize, &GlossaryEntry)> {
+        self.entries
+            .iter()
+            .filter_map(|e| {
+                let mut from = start;
+                while let Some(i) = s[from..].find(&e.term).map(|i| i + from) {
+                    let before = s[..i].chars().next_back();
+                    let after = s[i + e.term.len()..].chars().next();
+                    if is_word_boundary(before) && is_word_boundary(after) {
+                        return Some((i, e));
+                    }
+                    from = i + e.term.len();
+                }
+                None
+            })
+            // longer terms win, so that `REST API` isn't found as `API`
+            .min_by_key(|(i, e)| (*i, usize::MAX - e.term.len()))
+    }
+
+    /// The markup for a use of a term
+    fn term_node(&self, entry: &GlossaryEntry, link: bool) -> Option<ASTNode> {
+        let mut node = ASTNode::Text(entry.term.clone());
+        if entry.abbreviation {
+            let title = entry
+                .definition
+                .iter()
+                .map(ASTNode::plain_text)
+                .collect::<String>();
+            node = command(
+                "abbr",
+                vec![("title".to_string(), title.trim().to_string())],
+                vec![node],
+            );
+        }
+        if link {
+            node = command(
+                &BasicCommandType::Link.to_string(),
+                Vec::new(),
+                vec![
+                    ASTNode::Text(format!("#{}", entry.id)),
+                    ASTNode::ArgSeparator(String::new()),
+                    node,
+                ],
+            );
+        } else if !entry.abbreviation {
+            return None;
+        }
+        Some(node)
+    }
+
+    fn mark_text(&self, s: String, link: bool, used: &mut HashSet<String>) -> Vec<ASTNode> {
+        let mut res = Vec::new();
+        let mut rest_start = 0;
+        let mut from = 0;
+        while let Some((i, entry)) = self.find_term(&s, from) {
+            from = i + entry.term.len();
+            let first = used.insert(entry.term.clone());
+            if let Some(node) = self.term_node(entry, link && first) {
+                if i > rest_start {
+                    res.push(ASTNode::Text(s[rest_start..i].to_string()));
+                }
+                res.push(node);
+                rest_start = from;
+            }
+        }
+        if rest_start < s.len() {
+            res.push(ASTNode::Text(s[rest_start..].to_string()));
+        }
+        res
+    }
+
+    fn mark_inner(&self, nodes: &mut Vec<ASTNode>, link: bool, used: &mut HashSet<String>) {
+        for node in std::mem::take(nodes) {
+            match node {
+                ASTNode::Text(s) => nodes.extend(self.mark_text(s, link, used)),
+                ASTNode::CommandCall(mut c) => {
+                    let skip = c.is_verbatim()
+                        || is_heading(&c)
+                        || (c.backend.is_none()
+                            && (c.cmd == "abbr"
+                                || matches!(
+                                    c.cmd.parse(),
+                                    Ok(BasicCommandType::Link) | Ok(BasicCommandType::Glossary)
+                                )));
+                    if !skip {
+                        let mut prose = c.arguments.split_off(c.prose_start());
+                        self.mark_inner(&mut prose, link, used);
+                        c.arguments.append(&mut prose);
+                    }
+                    nodes.push(ASTNode::CommandCall(c));
+                }
+                node => nodes.push(node),
+            }
+        }
+    }
+
+    /// Marks the uses of the terms, see the module documentation
+    ///
+    /// Headings, links, verbatim commands and arguments that are not prose are left alone.
+    pub fn mark_terms(&self, nodes: &mut Vec<ASTNode>) {
+        if self.is_empty() {
+            return;
+        }
+        let link = has_glossary(nodes);
+        self.mark_inner(nodes, link, &mut HashSet::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glossary;
+    use crate::{parse, ASTNode};
+
+    fn nodes(s: &str) -> Vec<ASTNode> {
+        parse(s.to_string()).unwrap().nodes
+    }
+
+    fn is_abbr(node: &ASTNode) -> bool {
+        matches!(node, ASTNode::CommandCall(c) if c.cmd == "abbr")
+    }
+
+    #[test]
+    fn collect() {
+        let mut doc = nodes(
+            "[define API; Application programming interface][i [define{abbr = no} NASA; Space]]\
+             [define Term; A word][define API; Again][define x]",
+        );
+        let (glossary, warnings) = Glossary::collect(&mut doc);
+        assert_eq!(
+            warnings,
+            [
+                "`API` is defined more than once",
+                "`[define x]` needs a term and a definition"
+            ]
+        );
+        let terms = glossary
+            .sorted()
+            .iter()
+            .map(|e| (e.term.as_str(), e.id.as_str(), e.abbreviation))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            terms,
+            [
+                ("API", "gloss-api", true),
+                ("NASA", "gloss-nasa", false),
+                ("Term", "gloss-term", false)
+            ]
+        );
+        assert!(!doc.iter().any(|n| n.plain_text().contains("Application")));
+    }
+
+    #[test]
+    fn mark_terms() {
+        let mut doc =
+            nodes("[define API; Interface][define REST API; Rest]An API, the API and a REST API");
+        let (glossary, _) = Glossary::collect(&mut doc);
+        glossary.mark_terms(&mut doc);
+        assert_eq!(doc.iter().filter(|n| is_abbr(n)).count(), 3);
+        assert_eq!(doc[1].plain_text(), "API");
+        assert_eq!(doc.last().unwrap().plain_text(), "REST API");
+
+        // with a glossary, only the first use links to the entry
+        let mut doc = nodes("[define API; Interface]API API[glossary]");
+        let (glossary, _) = Glossary::collect(&mut doc);
+        glossary.mark_terms(&mut doc);
+        let links = doc
+            .iter()
+            .filter(|n| matches!(n, ASTNode::CommandCall(c) if c.cmd == "link"))
+            .count();
+        assert_eq!(links, 1);
+    }
+
+    #[test]
+    fn keys_and_targets_are_left_alone() {
+        let mut doc = nodes(
+            "[define API; Interface][img API.png; The API][ref API][link API; x][code API][sec1 API]",
+        );
+        let (glossary, _) = Glossary::collect(&mut doc);
+        glossary.mark_terms(&mut doc);
+        let arguments = |node: &ASTNode| match node {
+            ASTNode::CommandCall(c) => c.arguments.clone(),
+            node => panic!("expected a command, got {:?}", node),
+        };
+        let img = arguments(&doc[0]);
+        assert_eq!(img[0], ASTNode::Text("API.png".to_string()));
+        assert!(img.iter().any(is_abbr));
+        for node in &doc[1..] {
+            assert!(!arguments(node).iter().any(is_abbr), "{:?}", node);
+        }
+    }
+}
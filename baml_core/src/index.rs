@@ -0,0 +1,221 @@
+//! Book-style indexes
+//!
+//! `[index term]` marks a place in the text, `[index term!subterm]` a place for a sub-entry
+//! and `[index a; b]` both terms at once. Every marker gets an id (`index-<n>` in document order,
+//! unless it has one), and `Index` collects the terms with links to their markers.
+//! Indexes of several documents can be merged with `Index::extend`, so that one index covers all of them.
+
+use crate::{split_arguments, ASTNode, BasicCommandType, Command};
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct IndexTerm {
+    pub term: String,
+    /// Links to the markers of this term
+    pub locations: Vec<String>,
+    pub subterms: Vec<IndexTerm>,
+}
+
+impl IndexTerm {
+    fn sort_key(&self) -> (String, String) {
+        (self.term.to_lowercase(), self.term.clone())
+    }
+}
+
+/// The terms of an index, sorted alphabetically
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Index {
+    pub terms: Vec<IndexTerm>,
+}
+
+pub fn is_index_marker(cmd: &Command) -> bool {
+    cmd.backend.is_none() && cmd.cmd.parse() == Ok(BasicCommandType::Index)
+}
+
+/// The terms of a marker, each as the path of the term and its subterms
+pub fn marker_terms(cmd: &Command) -> Vec<Vec<String>> {
+    split_arguments(cmd.arguments.clone())
+        .iter()
+        .map(|arg| {
+            arg.iter()
+                .map(ASTNode::plain_text)
+                .collect::<String>()
+                .split('!')
+                .map(|t| t.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|path| path.iter().all(|t| !t.is_empty()))
+        .collect()
+}
+
+/// The position of the term, which is inserted in order if it's new
+fn position(terms: &mut Vec<IndexTerm>, term: &str) -> usize {
+    if let Some(i) = terms.iter().position(|t| t.term == term) {
+        return i;
+    }
+    let term = IndexTerm {
+        term: term.to_string(),
+        ..Default::default()
+    };
+    let i = terms.partition_point(|t| t.sort_key() < term.sort_key());
+    terms.insert(i, term);
+    i
+}
+
+fn insert(terms: &mut Vec<IndexTerm>, path: &[String], location: String) {
+    if let Some((first, rest)) = path.split_first() {
+        let i = position(terms, first);
+        if rest.is_empty() {
+            terms[i].locations.push(location);
+        } else {
+            insert(&mut terms[i].subterms, rest, location);
+        }
+    }
+}
+
+fn merge(terms: &mut Vec<IndexTerm>, other: Vec<IndexTerm>) {
+    for term in other {
+        let i = position(terms, &term.term);
+        terms[i].locations.extend(term.locations);
+        merge(&mut terms[i].subterms, term.subterms);
+    }
+}
+
+impl Index {
+    /// Assigns ids to the markers and collects them, linking to `<page>#<id>`
+    ///
+    /// `page` is empty for links within the document.
+    pub fn collect(nodes: &mut [ASTNode], page: &str) -> Self {
+        let mut res = Self::default();
+        res.collect_inner(nodes, page, &mut 0);
+        res
+    }
+
+    fn collect_inner(&mut self, nodes: &mut [ASTNode], page: &str, count: &mut usize) {
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if is_index_marker(c) {
+                    *count += 1;
+                    if c.attribute("id").is_none() {
+                        c.attributes
+                            .push(("id".to_string(), format!("index-{}", count)));
+                    }
+                    let location = format!("{}#{}", page, c.attribute("id").unwrap());
+                    for path in marker_terms(c) {
+                        self.add(&path, location.clone());
+                    }
+                } else {
+                    self.collect_inner(&mut c.arguments, page, count);
+                }
+            }
+        }
+    }
+
+    /// Adds a location for a term given as the path of the term and its subterms
+    pub fn add(&mut self, path: &[String], location: String) {
+        insert(&mut self.terms, path, location);
+    }
+
+    /// Adds the terms of another index, e.g. of another document
+    pub fn extend(&mut self, other: Index) {
+        merge(&mut self.terms, other.terms);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The terms grouped by their uppercase first letter
+    pub fn groups(&self) -> Vec<(String, Vec<&IndexTerm>)> {
+        let mut res: Vec<(String, Vec<&IndexTerm>)> = Vec::new();
+        for term in &self.terms {
+            let letter = term
+                .term
+                .chars()
+                .next()
+                .map(|c| c.to_uppercase().to_string())
+                .unwrap_or_default();
+            match res.last_mut() {
+                Some((l, terms)) if *l == letter => terms.push(term),
+                _ => res.push((letter, vec![term])),
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Index;
+    use crate::{parse, ASTNode};
+
+    fn collect(s: &str, page: &str) -> (Vec<ASTNode>, Index) {
+        let mut nodes = parse(s.to_string()).unwrap().nodes;
+        let index = Index::collect(&mut nodes, page);
+        (nodes, index)
+    }
+
+    /// The terms as `term: locations` lines, subterms indented
+    fn entries(index: &Index) -> Vec<String> {
+        fn add(res: &mut Vec<String>, terms: &[super::IndexTerm], indent: &str) {
+            for t in terms {
+                res.push(format!("{}{}: {}", indent, t.term, t.locations.join(" ")));
+                add(res, &t.subterms, &format!("{}  ", indent));
+            }
+        }
+        let mut res = Vec::new();
+        add(&mut res, &index.terms, "");
+        res
+    }
+
+    #[test]
+    fn collect_markers() {
+        let (nodes, index) = collect(
+            "a[index fox]b[i [index{id = dogs} dog!puppy; fox]][index !x]",
+            "",
+        );
+        assert_eq!(
+            entries(&index),
+            ["dog: ", "  puppy: #dogs", "fox: #index-1 #dogs"]
+        );
+        let ids = nodes
+            .iter()
+            .filter_map(|n| match n {
+                ASTNode::CommandCall(c) => c.attribute("id").map(str::to_string),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["index-1", "index-3"]);
+    }
+
+    #[test]
+    fn sorting() {
+        let (_, index) = collect("[index beta; Alpha; alpha; Émile; gamma; Zeta]", "");
+        let terms = index
+            .terms
+            .iter()
+            .map(|t| t.term.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(terms, ["Alpha", "alpha", "beta", "gamma", "Zeta", "Émile"]);
+        let letters = index
+            .groups()
+            .into_iter()
+            .map(|(l, terms)| format!("{}{}", l, terms.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(letters, ["A2", "B1", "G1", "Z1", "É1"]);
+    }
+
+    #[test]
+    fn extend() {
+        let (_, mut index) = collect("[index fox!red][index dog]", "a.html");
+        index.extend(collect("[index fox!red; cat]", "b.html").1);
+        assert_eq!(
+            entries(&index),
+            [
+                "cat: b.html#index-1",
+                "dog: a.html#index-2",
+                "fox: ",
+                "  red: a.html#index-1 b.html#index-1"
+            ]
+        );
+    }
+}
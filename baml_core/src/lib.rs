@@ -14,22 +14,35 @@ Text to be displayed
 
 # Comment
 Comment
+-> a '#' directly after a name (letters, digits, '_', '-', '.' or '@') doesn't start a comment,
+   so '[sec#intro ...]' or 'a#b' work as written
+-> with '!comments spaced', the '#' also has to be followed by whitespace (or end the line),
+   so '= #ff0000' is not a comment either (without it, it has to be escaped: '\#ff0000')
 
 [<Cmd> <Args>]
 (Within Text) A Command Call
 Args are separated with ';'
+-> whitespace after a ';' is kept (as an `ASTNode::ArgSeparator`), whitespace after <Cmd> is not
+-> generic commands join their arguments with that whitespace, so '[b foo; bar]' is 'foo bar' and '[b foo;bar]' is 'foobar'
 Cmd can end with a dict literal, denoting its attributes
 -> dict literals are '{' (key '=' value),* '}'
 -> Cmd can be backend@cmd for backend specific stuff
+-> Cmd can end with '.class' and '#id' shorthands (before the dict literal)
+    -> e.g. 'sec.note#intro' is 'sec{class = note; id = intro}'
+    -> classes are added in front of an explicit 'class', a different explicit 'id' is an error
+    -> backend specific 'tag.<name>' commands are namespaced: in 'html@tag.div.note', the name is 'tag.div'
 
 !<key> <value> <EOL>
 (At start of line) Setting Metadata
+-> setting 'link', 'style' or 'symbols' again appends the value on a new line, other keys are overwritten
 
 .<Cmd> <Args> <EOL>
 A Command Call on a single line
 */
 
 pub use self::parser::{get_metadata, parse, ParseCommandErr};
+use crate::callout::CalloutKind;
+use crate::quote::Quote;
 use std::collections::HashMap;
 use std::str::FromStr;
 
@@ -39,6 +52,8 @@ pub struct Command {
     pub cmd: String,
     pub attributes: Vec<(String, String)>,
     pub arguments: Vec<ASTNode>,
+    /// The source line the command starts on, `None` for generated commands
+    pub line: Option<usize>,
 }
 
 // todo:
@@ -50,10 +65,37 @@ pub struct Command {
 pub enum BasicCommandType {
     Bold,
     Italic,
+    Strike,
+    Underline,
+    Subscript,
+    Superscript,
+    SmallCaps,
+    Mark,
+    Code,
+    Kbd,
     Section(usize),
     VertSpace,
     HorSpace,
     Image,
+    Link,
+    Toc,
+    Footnote,
+    Figure,
+    ListOfFigures,
+    Math,
+    DisplayMath,
+    Equation,
+    Cite,
+    References,
+    Callout(CalloutKind),
+    DefinitionList,
+    Glossary,
+    Index,
+    PrintIndex,
+    Quote,
+    /// A task list item, the flag is whether it's done
+    Task(bool),
+    Symbol,
 }
 
 impl FromStr for BasicCommandType {
@@ -61,13 +103,43 @@ impl FromStr for BasicCommandType {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use self::BasicCommandType::*;
+        // names of html elements (like `s`, `code` or `dl`) are left to the generic commands,
+        // which have always turned into those elements
         Ok(match s {
             "b" => Bold,
             "i" => Italic,
+            "strike" => Strike,
+            "underline" => Underline,
+            "subscript" => Subscript,
+            "superscript" => Superscript,
+            "smallcaps" | "sc" => SmallCaps,
+            "highlight" => Mark,
+            "verb" => Code,
+            "key" => Kbd,
             "sec" => Section(0),
             "vspace" => VertSpace,
             "hspace" => HorSpace,
             "img" => Image,
+            "link" => Link,
+            "toc" => Toc,
+            "footnote" | "fn" => Footnote,
+            "fig" => Figure,
+            "listoffigures" | "lof" => ListOfFigures,
+            "math" => Math,
+            "displaymath" => DisplayMath,
+            "equation" => Equation,
+            "cite" => Cite,
+            "references" => References,
+            "callout" => Callout(CalloutKind::default()),
+            s if s.parse::<CalloutKind>().is_ok() => Callout(s.parse().unwrap()),
+            "deflist" => DefinitionList,
+            "glossary" => Glossary,
+            "index" => Index,
+            "printindex" => PrintIndex,
+            "quote" => Quote,
+            "todo" => Task(false),
+            "done" => Task(true),
+            "sym" => Symbol,
             s if s.starts_with("sec") && s[3..].chars().all(|c| c.is_ascii_digit()) => {
                 Section(s[3..].parse().unwrap())
             }
@@ -82,11 +154,38 @@ impl ToString for BasicCommandType {
         match self {
             Bold => "b",
             Italic => "i",
+            Strike => "strike",
+            Underline => "underline",
+            Subscript => "subscript",
+            Superscript => "superscript",
+            SmallCaps => "smallcaps",
+            Mark => "highlight",
+            Code => "verb",
+            Kbd => "key",
             Section(0) => "sec",
             Section(n) => return format!("sec{}", n),
             VertSpace => "vspace",
             HorSpace => "hspace",
             Image => "img",
+            Link => "link",
+            Toc => "toc",
+            Footnote => "footnote",
+            Figure => "fig",
+            ListOfFigures => "listoffigures",
+            Math => "math",
+            DisplayMath => "displaymath",
+            Equation => "equation",
+            Cite => "cite",
+            References => "references",
+            Callout(kind) => return kind.to_string(),
+            DefinitionList => "deflist",
+            Glossary => "glossary",
+            Index => "index",
+            PrintIndex => "printindex",
+            Quote => "quote",
+            Task(false) => "todo",
+            Task(true) => "done",
+            Symbol => "sym",
         }
         .to_string()
     }
@@ -96,6 +195,188 @@ impl ToString for BasicCommandType {
 pub enum ASTNode {
     Text(String),
     CommandCall(Command),
+    /// The boundary between two command arguments, holding the whitespace that followed the `;`
+    ///
+    /// Generic commands render it as the whitespace, commands that need the exact source
+    /// (like math) put the `;` back.
+    ArgSeparator(String),
+    /// A block quote, made from `[quote]` commands by `quote::build_quotes`
+    Quote(Quote),
+}
+
+impl ASTNode {
+    /// The text content of the node, without any markup
+    pub fn plain_text(&self) -> String {
+        match self {
+            ASTNode::Text(s) | ASTNode::ArgSeparator(s) => s.clone(),
+            ASTNode::CommandCall(c) => c.plain_text(),
+            ASTNode::Quote(q) => q.content.iter().map(ASTNode::plain_text).collect(),
+        }
+    }
+}
+
+/// Commands whose arguments are taken literally, so text transforms have to leave them alone
+const VERBATIM_COMMANDS: &[&str] = &[
+    "code",
+    "displaymath",
+    "equation",
+    "kbd",
+    "key",
+    "math",
+    "pre",
+    "samp",
+    "script",
+    "style",
+    "textarea",
+    "verb",
+];
+
+/// Commands whose arguments are names (of labels, bibliography entries or index terms) rather than text
+const KEY_COMMANDS: &[&str] = &["cite", "index", "label", "ref"];
+
+impl Command {
+    /// The text content of the arguments, without any markup
+    pub fn plain_text(&self) -> String {
+        self.arguments.iter().map(ASTNode::plain_text).collect()
+    }
+
+    /// Whether the arguments are meant literally (like code)
+    ///
+    /// For backend specific commands, only the last part of a namespaced name counts (`html@tag.pre`)
+    pub fn is_verbatim(&self) -> bool {
+        let name = match self.backend {
+            Some(_) => self.cmd.rsplit('.').next().unwrap(),
+            None => &self.cmd,
+        };
+        VERBATIM_COMMANDS.contains(&name)
+    }
+
+    /// The index of the first argument node that is prose, so text transforms have to skip the ones before
+    ///
+    /// Link targets, image sources and keys (like in `[ref]`) are not prose.
+    pub fn prose_start(&self) -> usize {
+        if self.backend.is_some() {
+            return 0;
+        }
+        let first_separator = || {
+            self.arguments
+                .iter()
+                .position(|n| matches!(n, ASTNode::ArgSeparator(_)))
+                .unwrap_or(self.arguments.len())
+        };
+        match self.cmd.parse() {
+            Ok(BasicCommandType::Link) | Ok(BasicCommandType::Image) => first_separator(),
+            _ if KEY_COMMANDS.contains(&self.cmd.as_str()) => self.arguments.len(),
+            _ => 0,
+        }
+    }
+
+    /// The value of the first attribute with the key
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Interprets an attribute as a switch, see `parse_flag`
+    pub fn flag(&self, key: &str) -> Option<bool> {
+        parse_flag(self.attribute(key)?)
+    }
+}
+
+/// Interprets a value as a switch (`yes`/`no`, `true`/`false` or `on`/`off`)
+pub fn parse_flag(s: &str) -> Option<bool> {
+    match s.trim() {
+        "yes" | "true" | "on" => Some(true),
+        "no" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Removes leading and trailing whitespace from the text at the ends of the nodes
+pub fn trim_nodes(mut nodes: Vec<ASTNode>) -> Vec<ASTNode> {
+    if let Some(ASTNode::Text(s)) = nodes.first_mut() {
+        *s = s.trim_start().to_string();
+    }
+    if let Some(ASTNode::Text(s)) = nodes.last_mut() {
+        *s = s.trim_end().to_string();
+    }
+    nodes.retain(|n| !matches!(n, ASTNode::Text(s) if s.is_empty()));
+    nodes
+}
+
+/// Whether the command may only appear once in the output, like a footnote
+fn is_one_off(cmd: &Command) -> bool {
+    cmd.backend.is_none()
+        && (cmd.cmd == "label"
+            || matches!(
+                cmd.cmd.parse(),
+                Ok(BasicCommandType::Footnote) | Ok(BasicCommandType::Index)
+            ))
+}
+
+/// A copy of nodes that are shown again elsewhere, like a heading in a table of contents
+///
+/// Footnotes, index markers and labels are left out and ids are removed, so that nothing is duplicated.
+pub fn repeatable_nodes(nodes: &[ASTNode]) -> Vec<ASTNode> {
+    nodes
+        .iter()
+        .filter_map(|node| match node {
+            ASTNode::CommandCall(c) if is_one_off(c) => None,
+            ASTNode::CommandCall(c) => Some(ASTNode::CommandCall(Command {
+                attributes: c
+                    .attributes
+                    .iter()
+                    .filter(|(k, _)| k != "id")
+                    .cloned()
+                    .collect(),
+                arguments: repeatable_nodes(&c.arguments),
+                ..c.clone()
+            })),
+            node => Some(node.clone()),
+        })
+        .collect()
+}
+
+/// The source of the arguments of a verbatim command (like math or code), with the `;` that separated them put back
+pub fn verbatim_source(args: &[ASTNode]) -> String {
+    args.iter()
+        .map(|node| match node {
+            ASTNode::ArgSeparator(ws) => format!(";{}", ws),
+            node => node.plain_text(),
+        })
+        .collect()
+}
+
+/// Splits flat command arguments at the `ArgSeparator`s
+pub fn split_arguments(args: Vec<ASTNode>) -> Vec<Vec<ASTNode>> {
+    if args.is_empty() {
+        return Vec::new();
+    }
+    let mut res = vec![Vec::new()];
+    for node in args {
+        match node {
+            ASTNode::ArgSeparator(_) => res.push(Vec::new()),
+            node => res.last_mut().unwrap().push(node),
+        }
+    }
+    res
+}
+
+/// Splits flat command arguments into the first one and the rest (which keeps its separators)
+pub fn split_first_argument(mut args: Vec<ASTNode>) -> (Vec<ASTNode>, Vec<ASTNode>) {
+    match args
+        .iter()
+        .position(|n| matches!(n, ASTNode::ArgSeparator(_)))
+    {
+        Some(i) => {
+            let rest = args.split_off(i + 1);
+            args.pop();
+            (args, rest)
+        }
+        None => (args, Vec::new()),
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -104,6 +385,13 @@ pub struct AST {
     pub nodes: Vec<ASTNode>,
 }
 
+impl AST {
+    /// Interprets a metadata value as a switch, see `parse_flag`
+    pub fn metadata_flag(&self, key: &str) -> Option<bool> {
+        parse_flag(self.metadata.get(key)?)
+    }
+}
+
 pub trait Backend {
     type Rendered;
     type Output;
@@ -114,6 +402,11 @@ pub trait Backend {
 
     fn run_command(&mut self, cmd: Command) -> Option<Self::Rendered>;
 
+    /// Renders a block quote, by default as the `[quote]` command it was made from
+    fn emit_quote(&mut self, quote: Quote) -> Option<Self::Rendered> {
+        self.run_command(quote.into_command())
+    }
+
     fn run_basic_command(
         &mut self,
         cmd: BasicCommandType,
@@ -125,12 +418,15 @@ pub trait Backend {
             cmd: cmd.to_string(),
             attributes: attrs,
             arguments: args,
+            line: None,
         })
     }
 
     fn handle_node(&mut self, node: ASTNode) -> Option<Self::Rendered> {
         Some(match node {
             ASTNode::Text(s) => self.emit_text(s),
+            ASTNode::ArgSeparator(s) => self.emit_text(s),
+            ASTNode::Quote(q) => self.emit_quote(q)?,
             ASTNode::CommandCall(c) => {
                 if c.backend.is_none() || c.backend.as_deref() == Some(Self::backend_id()) {
                     self.run_command(c)?
@@ -144,5 +440,26 @@ pub trait Backend {
     fn compile_ast(&mut self, ast: AST) -> Self::Output;
 }
 
+pub mod autolink;
+pub mod bibliography;
+pub mod bibtex;
+pub mod callout;
+pub mod figures;
+pub mod footnotes;
+pub mod glossary;
+pub mod index;
+pub mod links;
+pub mod math;
+pub mod numbering;
+pub mod outline;
 mod parser;
+pub mod quote;
+pub mod refs;
+pub mod shortcodes;
+pub mod slug;
+pub mod style;
+pub mod stylesheet;
+pub mod tasks;
+pub mod typography;
+pub mod values;
 // pub mod template;
@@ -0,0 +1,175 @@
+//! Link targets and reference-style links
+//!
+//! `[link https://example.com; text]` links directly, while `[link @docs; text]` refers to a target
+//! that is defined once, either as metadata (`!link docs https://...`)
+//! or in a definitions block (`[linkdefs{docs = https://...; api = https://...}]`).
+
+use crate::{ASTNode, BasicCommandType, AST};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct LinkDefinitions {
+    pub targets: HashMap<String, String>,
+}
+
+impl LinkDefinitions {
+    /// Reads the `!link <name> <target>` metadata lines, returns warnings for malformed ones
+    pub fn from_metadata(meta: &HashMap<String, String>) -> (Self, Vec<String>) {
+        let mut res = Self::default();
+        let mut warnings = Vec::new();
+        for line in meta.get("link").map(String::as_str).unwrap_or("").lines() {
+            let mut spl = line.trim().splitn(2, char::is_whitespace);
+            let name = spl.next().unwrap();
+            match spl.next().map(str::trim) {
+                Some(target) if !target.is_empty() => {
+                    res.targets.insert(name.to_string(), target.to_string());
+                }
+                _ => warnings.push(format!("`!link {}` is missing a target", line.trim())),
+            }
+        }
+        (res, warnings)
+    }
+
+    /// Collects the definitions from all `linkdefs` blocks and removes the blocks
+    pub fn collect(&mut self, nodes: &mut Vec<ASTNode>) {
+        let targets = &mut self.targets;
+        nodes.retain(|node| match node {
+            ASTNode::CommandCall(c) if c.backend.is_none() && c.cmd == "linkdefs" => {
+                targets.extend(c.attributes.iter().cloned());
+                false
+            }
+            _ => true,
+        });
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                self.collect(&mut c.arguments);
+            }
+        }
+    }
+
+    /// Looks up `@name` references, other targets are returned as-is
+    pub fn resolve<'a>(&'a self, target: &'a str) -> Option<&'a str> {
+        match target.strip_prefix('@') {
+            Some(name) => self.targets.get(name).map(String::as_str),
+            None => Some(target),
+        }
+    }
+
+    fn resolve_in(&self, nodes: &mut Vec<ASTNode>, warnings: &mut Vec<String>) {
+        for node in nodes {
+            let cmd = match node {
+                ASTNode::CommandCall(c) => c,
+                _ => continue,
+            };
+            self.resolve_in(&mut cmd.arguments, warnings);
+            if cmd.backend.is_some() || cmd.cmd.parse() != Ok(BasicCommandType::Link) {
+                continue;
+            }
+            // only the target is replaced, the text keeps its separator
+            let end = cmd
+                .arguments
+                .iter()
+                .position(|n| matches!(n, ASTNode::ArgSeparator(_)))
+                .unwrap_or(cmd.arguments.len());
+            let target_text = cmd.arguments[..end]
+                .iter()
+                .map(ASTNode::plain_text)
+                .collect::<String>();
+            let target_text = target_text.trim();
+            match self.resolve(target_text) {
+                Some(url) if target_text.starts_with('@') => {
+                    let url = ASTNode::Text(url.to_string());
+                    cmd.arguments.splice(..end, std::iter::once(url));
+                }
+                Some(_) => (),
+                None => warnings.push(format!("undefined link reference `{}`", target_text)),
+            }
+        }
+    }
+}
+
+/// Collects all link definitions of the document and replaces references with their targets
+///
+/// Returns warnings, e.g. for undefined references (which are left as they are)
+pub fn resolve_links(ast: &mut AST) -> Vec<String> {
+    let (mut defs, mut warnings) = LinkDefinitions::from_metadata(&ast.metadata);
+    defs.collect(&mut ast.nodes);
+    defs.resolve_in(&mut ast.nodes, &mut warnings);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_links;
+    use crate::{parse, ASTNode, Command};
+
+    fn links(source: &str) -> (Vec<Vec<ASTNode>>, Vec<String>) {
+        let mut ast = parse(source.to_string()).unwrap();
+        let warnings = resolve_links(&mut ast);
+        let links = ast
+            .nodes
+            .into_iter()
+            .filter_map(|n| match n {
+                ASTNode::CommandCall(Command { arguments, .. }) => Some(arguments),
+                _ => None,
+            })
+            .collect();
+        (links, warnings)
+    }
+
+    fn text(s: &str) -> ASTNode {
+        ASTNode::Text(s.to_string())
+    }
+
+    #[test]
+    fn metadata() {
+        let (links, warnings) =
+            links("!link docs https://docs.rs\n[link @docs;  the docs][link https://x.org]");
+        assert_eq!(
+            links,
+            vec![
+                vec![
+                    text("https://docs.rs"),
+                    ASTNode::ArgSeparator("  ".to_string()),
+                    text("the docs")
+                ],
+                vec![text("https://x.org")],
+            ]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn definition_blocks() {
+        let (links, warnings) = links("[linkdefs{api = https://api.example.com}]x[link @api; api]");
+        assert_eq!(
+            links,
+            vec![vec![
+                text("https://api.example.com"),
+                ASTNode::ArgSeparator(" ".to_string()),
+                text("api")
+            ]]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn undefined_references() {
+        let (links, warnings) = links("!link\tbroken\n[link @nope; x]");
+        assert_eq!(
+            links,
+            vec![vec![
+                text("@nope"),
+                ASTNode::ArgSeparator(" ".to_string()),
+                text("x")
+            ]]
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "`!link broken` is missing a target".to_string(),
+                "undefined link reference `@nope`".to_string(),
+            ]
+        );
+    }
+}
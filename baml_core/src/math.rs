@@ -0,0 +1,610 @@
+//! Formulas written in a subset of LaTeX
+//!
+//! `[math ...]` is inline, `[displaymath ...]` is a block and `[equation ...]` is a numbered block
+//! that can be labeled. The supported subset covers numbers, letters, operators, greek letters
+//! and common symbols, `^` and `_`, `\frac`, `\sqrt`, `\text`, `\left ... \right`,
+//! accents like `\hat`, font commands like `\mathbf` and spacing like `\,` and `\quad`.
+//! Brackets and semicolons have to be escaped (`\[`, `\]`, `\;`) since they are baml syntax,
+//! which is why `\[`, `\]` are brackets and `\;` is a space, like in LaTeX.
+
+use crate::{BasicCommandType, Command};
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MathNode {
+    Ident(String),
+    Number(String),
+    Operator(String),
+    /// An operator whose scripts are limits, like `\sum` or `\lim`
+    LargeOperator(String),
+    Text(String),
+    /// Horizontal space of the given width, e.g. `0.167em`
+    Space(String),
+    Row(Vec<MathNode>),
+    Frac(Box<MathNode>, Box<MathNode>),
+    /// The radicand and an optional index
+    Root(Box<MathNode>, Option<Box<MathNode>>),
+    Scripts {
+        base: Box<MathNode>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    /// An accent (given as its character) above the base
+    Accent(Box<MathNode>, String),
+    /// `\left<open> ... \right<close>`, where a missing delimiter (`.`) is empty
+    Fenced(String, Vec<MathNode>, String),
+    /// Content in a font like `bold` or `double-struck`
+    Variant(String, Box<MathNode>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MathError {
+    pub source: String,
+    /// The char index where the error was found
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid math `{}` at position {}: {}",
+            self.source, self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for MathError {}
+
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("emptyset", "∅"),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "⋅"),
+    ("ast", "∗"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("propto", "∝"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("leftrightarrow", "↔"),
+    ("Leftrightarrow", "⇔"),
+    ("mapsto", "↦"),
+    ("implies", "⟹"),
+    ("iff", "⟺"),
+    ("ldots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("mid", "∣"),
+    ("parallel", "∥"),
+    ("perp", "⊥"),
+    ("angle", "∠"),
+    ("prime", "′"),
+];
+
+const LARGE_OPERATORS: &[(&str, &str)] = &[
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("oint", "∮"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+];
+
+/// Functions that are written upright
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "arg", "deg", "dim", "ker", "gcd",
+];
+
+/// Functions whose scripts are limits
+const LIMIT_FUNCTIONS: &[&str] = &["lim", "max", "min", "sup", "inf", "det", "Pr"];
+
+const ACCENTS: &[(&str, &str)] = &[
+    ("hat", "^"),
+    ("bar", "¯"),
+    ("overline", "‾"),
+    ("vec", "→"),
+    ("dot", "˙"),
+    ("ddot", "¨"),
+    ("tilde", "~"),
+];
+
+const VARIANTS: &[(&str, &str)] = &[
+    ("mathbf", "bold"),
+    ("mathit", "italic"),
+    ("mathrm", "normal"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+    ("mathfrak", "fraktur"),
+    ("mathsf", "sans-serif"),
+    ("mathtt", "monospace"),
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.167em"),
+    (":", "0.222em"),
+    (";", "0.278em"),
+    ("!", "-0.167em"),
+    (" ", "0.333em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+fn lookup<'a>(table: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    table.iter().find(|(k, _)| *k == name).map(|(_, v)| *v)
+}
+
+/// What ends the row that is being parsed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum RowEnd {
+    Eof,
+    Brace,
+    Right,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, MathError> {
+        Err(MathError {
+            source: self.source.to_string(),
+            position: self.pos,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads the name after a `\`, which is either letters or a single other character
+    fn command_name(&mut self) -> Result<String, MathError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            match self.peek() {
+                Some(_) => self.pos += 1,
+                None => return self.error("`\\` at the end"),
+            }
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn is_at_right(&self) -> bool {
+        self.chars[self.pos..].starts_with(&['\\', 'r', 'i', 'g', 'h', 't'])
+            && !self
+                .chars
+                .get(self.pos + 6)
+                .is_some_and(|c| c.is_ascii_alphabetic())
+    }
+
+    fn parse_row(&mut self, end: RowEnd) -> Result<Vec<MathNode>, MathError> {
+        let mut res = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None if end == RowEnd::Eof => return Ok(res),
+                None => return self.error("unexpected end, a `}` or `\\right` is missing"),
+                Some('}') if end == RowEnd::Brace => {
+                    self.pos += 1;
+                    return Ok(res);
+                }
+                Some('}') => return self.error("unmatched `}`"),
+                Some('\\') if self.is_at_right() => {
+                    if end == RowEnd::Right {
+                        return Ok(res);
+                    }
+                    return self.error("`\\right` without `\\left`");
+                }
+                Some('^') | Some('_') => return self.error("script without a base"),
+                Some(_) => {
+                    let atom = self.parse_atom()?;
+                    res.push(self.parse_scripts(atom)?);
+                }
+            }
+        }
+    }
+
+    /// Parses the `^` and `_` after `base`
+    fn parse_scripts(&mut self, base: MathNode) -> Result<MathNode, MathError> {
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_whitespace();
+            let slot = match self.peek() {
+                Some('_') => &mut sub,
+                Some('^') => &mut sup,
+                _ => break,
+            };
+            if slot.is_some() {
+                return self.error("double script");
+            }
+            self.pos += 1;
+            *slot = Some(Box::new(self.parse_argument()?));
+        }
+        if sub.is_none() && sup.is_none() {
+            return Ok(base);
+        }
+        Ok(MathNode::Scripts {
+            base: Box::new(base),
+            sub,
+            sup,
+        })
+    }
+
+    /// Parses the argument of a command or script, a group or a single atom (a single digit for numbers)
+    fn parse_argument(&mut self) -> Result<MathNode, MathError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => self.error("missing argument"),
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                Ok(MathNode::Number(c.to_string()))
+            }
+            Some('^') | Some('_') | Some('}') => self.error("missing argument"),
+            Some(_) => self.parse_atom(),
+        }
+    }
+
+    /// Reads the content of a `{...}` group as raw text
+    fn raw_group(&mut self) -> Result<String, MathError> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return self.error("expected `{`");
+        }
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return self.error("unexpected end, a `}` is missing"),
+                Some('{') => depth += 1,
+                Some('}') if depth == 0 => break,
+                Some('}') => depth -= 1,
+                Some('\\') => self.pos += 1,
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        let res = self.chars[start..self.pos].iter().collect::<String>();
+        self.pos += 1;
+        Ok(res.replace("\\{", "{").replace("\\}", "}"))
+    }
+
+    /// Reads the delimiter after `\left` or `\right`
+    fn delimiter(&mut self) -> Result<String, MathError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => self.error("missing delimiter"),
+            Some('\\') => {
+                self.pos += 1;
+                let name = self.command_name()?;
+                match name.as_str() {
+                    "{" | "}" | "[" | "]" => Ok(name),
+                    "|" => Ok("‖".to_string()),
+                    name => match lookup(OPERATORS, name) {
+                        Some(s) => Ok(s.to_string()),
+                        None => self.error(format!("`\\{}` is not a delimiter", name)),
+                    },
+                }
+            }
+            Some('.') => {
+                self.pos += 1;
+                Ok(String::new())
+            }
+            Some(c) => {
+                self.pos += 1;
+                Ok(c.to_string())
+            }
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<MathNode, MathError> {
+        self.skip_whitespace();
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("unexpected end"),
+        };
+        self.pos += 1;
+        Ok(match c {
+            '{' => MathNode::Row(self.parse_row(RowEnd::Brace)?),
+            '\\' => return self.parse_command(),
+            c if c.is_ascii_digit() => {
+                let start = self.pos - 1;
+                while self.peek().is_some_and(|c| c.is_ascii_digit())
+                    || (self.peek() == Some('.')
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_some_and(|c| c.is_ascii_digit()))
+                {
+                    self.pos += 1;
+                }
+                MathNode::Number(self.chars[start..self.pos].iter().collect())
+            }
+            c if c.is_alphabetic() => MathNode::Ident(c.to_string()),
+            '\'' => MathNode::Operator("′".to_string()),
+            '-' => MathNode::Operator("−".to_string()),
+            '*' => MathNode::Operator("∗".to_string()),
+            c => MathNode::Operator(c.to_string()),
+        })
+    }
+
+    /// Parses a command, the `\` has already been read
+    fn parse_command(&mut self) -> Result<MathNode, MathError> {
+        let start = self.pos - 1;
+        let name = self.command_name()?;
+        let name = name.as_str();
+        if let Some(s) = lookup(IDENTIFIERS, name) {
+            return Ok(MathNode::Ident(s.to_string()));
+        }
+        if let Some(s) = lookup(OPERATORS, name) {
+            return Ok(MathNode::Operator(s.to_string()));
+        }
+        if let Some(s) = lookup(LARGE_OPERATORS, name) {
+            return Ok(MathNode::LargeOperator(s.to_string()));
+        }
+        if let Some(s) = lookup(SPACES, name) {
+            return Ok(MathNode::Space(s.to_string()));
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok(MathNode::Ident(name.to_string()));
+        }
+        if LIMIT_FUNCTIONS.contains(&name) {
+            return Ok(MathNode::LargeOperator(name.to_string()));
+        }
+        if let Some(s) = lookup(ACCENTS, name) {
+            let base = self.parse_argument()?;
+            return Ok(MathNode::Accent(Box::new(base), s.to_string()));
+        }
+        if let Some(s) = lookup(VARIANTS, name) {
+            let content = self.parse_argument()?;
+            return Ok(MathNode::Variant(s.to_string(), Box::new(content)));
+        }
+        Ok(match name {
+            "{" | "}" | "[" | "]" | "#" | "%" | "&" | "$" | "_" => {
+                MathNode::Operator(name.to_string())
+            }
+            "|" => MathNode::Operator("‖".to_string()),
+            "frac" => {
+                let num = self.parse_argument()?;
+                let den = self.parse_argument()?;
+                MathNode::Frac(Box::new(num), Box::new(den))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                let index = if self.chars[self.pos..].starts_with(&['\\', '[']) {
+                    self.pos += 2;
+                    let index_start = self.pos;
+                    while !self.chars[self.pos..].starts_with(&['\\', ']']) {
+                        if self.pos == self.chars.len() {
+                            return self.error("unexpected end, a `\\]` is missing");
+                        }
+                        self.pos += 1;
+                    }
+                    let index = self.chars[index_start..self.pos].iter().collect::<String>();
+                    self.pos += 2;
+                    Some(Box::new(MathNode::Row(parse_math(&index)?)))
+                } else {
+                    None
+                };
+                MathNode::Root(Box::new(self.parse_argument()?), index)
+            }
+            "text" | "mbox" => MathNode::Text(self.raw_group()?),
+            "operatorname" => MathNode::Ident(self.raw_group()?),
+            "left" => {
+                let open = self.delimiter()?;
+                let content = self.parse_row(RowEnd::Right)?;
+                // skip `\right`
+                self.pos += 6;
+                let close = self.delimiter()?;
+                MathNode::Fenced(open, content, close)
+            }
+            "\\" => {
+                self.pos = start;
+                return self.error("line breaks are not supported");
+            }
+            name => {
+                self.pos = start;
+                return self.error(format!("unknown command `\\{}`", name));
+            }
+        })
+    }
+}
+
+/// Parses a formula
+pub fn parse_math(s: &str) -> Result<Vec<MathNode>, MathError> {
+    let mut p = Parser {
+        source: s,
+        chars: s.chars().collect(),
+        pos: 0,
+    };
+    p.parse_row(RowEnd::Eof)
+}
+
+pub fn is_equation(cmd: &Command) -> bool {
+    cmd.backend.is_none() && cmd.cmd.parse() == Ok(BasicCommandType::Equation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_math, MathNode};
+
+    fn ident(s: &str) -> MathNode {
+        MathNode::Ident(s.to_string())
+    }
+
+    fn number(s: &str) -> MathNode {
+        MathNode::Number(s.to_string())
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            parse_math("x_i^2 a^{10}").unwrap(),
+            vec![
+                MathNode::Scripts {
+                    base: Box::new(ident("x")),
+                    sub: Some(Box::new(ident("i"))),
+                    sup: Some(Box::new(number("2"))),
+                },
+                MathNode::Scripts {
+                    base: Box::new(ident("a")),
+                    sub: None,
+                    sup: Some(Box::new(MathNode::Row(vec![number("10")]))),
+                },
+            ]
+        );
+        // a single digit is the argument, like in LaTeX
+        assert_eq!(parse_math("2^10").unwrap()[1], number("0"),);
+    }
+
+    #[test]
+    fn fractions_and_roots() {
+        assert_eq!(
+            parse_math(r"\frac12 \frac{a}{b + 1}").unwrap(),
+            vec![
+                MathNode::Frac(Box::new(number("1")), Box::new(number("2"))),
+                MathNode::Frac(
+                    Box::new(MathNode::Row(vec![ident("a")])),
+                    Box::new(MathNode::Row(vec![
+                        ident("b"),
+                        MathNode::Operator("+".to_string()),
+                        number("1"),
+                    ])),
+                ),
+            ]
+        );
+        assert_eq!(
+            parse_math(r"\sqrt x \sqrt\[n\]{2}").unwrap(),
+            vec![
+                MathNode::Root(Box::new(ident("x")), None),
+                MathNode::Root(
+                    Box::new(MathNode::Row(vec![number("2")])),
+                    Some(Box::new(MathNode::Row(vec![ident("n")]))),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let position = |s: &str| parse_math(s).unwrap_err().position;
+        assert_eq!(position(r"a \\ b"), 2);
+        assert_eq!(position(r"x + \foo"), 4);
+        assert_eq!(position("^2"), 0);
+        assert_eq!(position("x^2^3"), 3);
+        assert_eq!(position("{a"), 2);
+        assert_eq!(position("a}"), 1);
+        assert_eq!(position(r"\frac{a}"), 8);
+        assert_eq!(position(r"\sqrt\[n"), 8);
+        let err = parse_math(r"a \\ b").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r"invalid math `a \\ b` at position 2: line breaks are not supported"
+        );
+    }
+}
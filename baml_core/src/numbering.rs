@@ -0,0 +1,359 @@
+//! Hierarchical section numbers like `2.3.1`
+//!
+//! Numbering is opt-in, e.g. with `!numbering arabic alpha roman`, which gives the format of each level
+//! (the last one is used for deeper levels); `!numbering yes` numbers every level in arabic.
+//! Headings with `{numbered = no}` don't get a number and don't advance the counters.
+//! The number is stored in the `number` attribute of the heading.
+
+use crate::slug::is_heading;
+use crate::values::{parse_value, ValueError};
+use crate::{parse_flag, ASTNode, BasicCommandType, Command};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum NumberFormat {
+    #[default]
+    Arabic,
+    /// `a`, `b`, ..., `z`, `aa`, ...
+    Alpha,
+    /// `A`, `B`, ...
+    UpperAlpha,
+    /// `i`, `ii`, ...
+    Roman,
+    /// `I`, `II`, ...
+    UpperRoman,
+}
+
+impl FromStr for NumberFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::NumberFormat::*;
+        Ok(match s {
+            "arabic" | "1" => Arabic,
+            "alpha" | "a" => Alpha,
+            "Alpha" | "A" => UpperAlpha,
+            "roman" | "i" => Roman,
+            "Roman" | "I" => UpperRoman,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl ToString for NumberFormat {
+    fn to_string(&self) -> String {
+        use self::NumberFormat::*;
+        match self {
+            Arabic => "arabic",
+            Alpha => "alpha",
+            UpperAlpha => "Alpha",
+            Roman => "roman",
+            UpperRoman => "Roman",
+        }
+        .to_string()
+    }
+}
+
+fn alpha(mut n: usize) -> String {
+    let mut res = Vec::new();
+    while n > 0 {
+        n -= 1;
+        res.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    res.iter().rev().collect()
+}
+
+fn roman(mut n: usize) -> String {
+    const DIGITS: &[(usize, &str)] = &[
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut res = String::new();
+    for (value, digits) in DIGITS {
+        while n >= *value {
+            res.push_str(digits);
+            n -= value;
+        }
+    }
+    res
+}
+
+impl NumberFormat {
+    /// Formats the number, zero (a skipped level) is always `0`
+    pub fn format(self, n: usize) -> String {
+        use self::NumberFormat::*;
+        if n == 0 {
+            return "0".to_string();
+        }
+        match self {
+            Arabic => n.to_string(),
+            Alpha => alpha(n),
+            UpperAlpha => alpha(n).to_uppercase(),
+            Roman => roman(n),
+            UpperRoman => roman(n).to_uppercase(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Numbering {
+    /// The format of each level, the last one is used for all deeper levels
+    pub formats: Vec<NumberFormat>,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Self {
+            formats: vec![NumberFormat::Arabic],
+        }
+    }
+}
+
+impl FromStr for Numbering {
+    type Err = ();
+
+    /// Parses a whitespace separated list of formats
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let formats = s
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if formats.is_empty() {
+            return Err(());
+        }
+        Ok(Self { formats })
+    }
+}
+
+impl Numbering {
+    /// Reads `!numbering`, `None` if it is missing or turned off with `!numbering no`
+    pub fn from_metadata(meta: &HashMap<String, String>) -> Result<Option<Self>, ValueError> {
+        let v = match meta.get("numbering") {
+            Some(v) => v.trim(),
+            None => return Ok(None),
+        };
+        match parse_flag(v) {
+            Some(true) => Ok(Some(Self::default())),
+            Some(false) => Ok(None),
+            None => parse_value(
+                "!numbering",
+                "value",
+                v,
+                "`yes`, `no` or a list of `arabic`, `alpha`, `Alpha`, `roman` and `Roman`",
+            )
+            .map(Some),
+        }
+    }
+
+    /// The format of the level, arabic if there are no formats at all
+    pub fn format(&self, level: usize) -> NumberFormat {
+        self.formats
+            .get(level)
+            .or_else(|| self.formats.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Formats the counters of all levels, e.g. `2.c.i`
+    pub fn format_counters(&self, counters: &[usize]) -> String {
+        counters
+            .iter()
+            .enumerate()
+            .map(|(level, n)| self.format(level).format(*n))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn apply_inner(&self, nodes: &mut [ASTNode], counters: &mut Vec<usize>) {
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if !is_heading(c) {
+                    self.apply_inner(&mut c.arguments, counters);
+                    continue;
+                }
+                let numbered = c.flag("numbered") != Some(false);
+                c.attributes
+                    .retain(|(k, _)| k != "numbered" && k != "number");
+                if !numbered {
+                    continue;
+                }
+                let level = match c.cmd.parse() {
+                    Ok(BasicCommandType::Section(n)) => n,
+                    _ => 0,
+                };
+                counters.resize(level + 1, 0);
+                counters[level] += 1;
+                c.attributes
+                    .push(("number".to_string(), self.format_counters(counters)));
+            }
+        }
+    }
+
+    /// Sets the `number` attribute of all headings that aren't `{numbered = no}`
+    pub fn apply(&self, nodes: &mut [ASTNode]) {
+        self.apply_inner(nodes, &mut Vec::new());
+    }
+}
+
+fn number_sequentially_inner(
+    nodes: &mut [ASTNode],
+    is_target: fn(&Command) -> bool,
+    id_prefix: &str,
+    count: &mut usize,
+) {
+    for node in nodes {
+        if let ASTNode::CommandCall(c) = node {
+            if is_target(c) {
+                let numbered = c.flag("numbered") != Some(false);
+                c.attributes
+                    .retain(|(k, _)| k != "numbered" && k != "number");
+                if numbered {
+                    *count += 1;
+                    c.attributes.push(("number".to_string(), count.to_string()));
+                    if c.attribute("id").is_none() {
+                        c.attributes
+                            .push(("id".to_string(), format!("{}-{}", id_prefix, count)));
+                    }
+                }
+            }
+            number_sequentially_inner(&mut c.arguments, is_target, id_prefix, count);
+        }
+    }
+}
+
+/// Numbers the commands in document order, skipping those that are `{numbered = no}`
+///
+/// Numbered commands without an id get `<id_prefix>-<number>`, returns how many were numbered.
+/// This should run before heading ids are assigned, so that those avoid these ids.
+pub fn number_sequentially(
+    nodes: &mut [ASTNode],
+    is_target: fn(&Command) -> bool,
+    id_prefix: &str,
+) -> usize {
+    let mut count = 0;
+    number_sequentially_inner(nodes, is_target, id_prefix, &mut count);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{number_sequentially, NumberFormat, Numbering};
+    use crate::{parse, ASTNode};
+    use std::collections::HashMap;
+
+    fn numbers(numbering: &Numbering, source: &str) -> Vec<Option<String>> {
+        let mut nodes = parse(source.to_string()).unwrap().nodes;
+        numbering.apply(&mut nodes);
+        nodes
+            .iter()
+            .map(|n| match n {
+                ASTNode::CommandCall(c) => c.attribute("number").map(str::to_string),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn some(v: &[&str]) -> Vec<Option<String>> {
+        v.iter().map(|s| Some(s.to_string())).collect()
+    }
+
+    #[test]
+    fn formats() {
+        use super::NumberFormat::*;
+        assert_eq!(Alpha.format(1), "a");
+        assert_eq!(Alpha.format(27), "aa");
+        assert_eq!(UpperAlpha.format(52), "AZ");
+        assert_eq!(Roman.format(1994), "mcmxciv");
+        assert_eq!(UpperRoman.format(4), "IV");
+        assert_eq!(Arabic.format(12), "12");
+        assert_eq!(Roman.format(0), "0");
+        assert_eq!("Alpha".parse(), Ok(UpperAlpha));
+        assert_eq!("i".parse(), Ok(Roman));
+        assert_eq!("greek".parse::<NumberFormat>(), Err(()));
+    }
+
+    #[test]
+    fn levels() {
+        let numbering = "arabic alpha roman".parse::<Numbering>().unwrap();
+        assert_eq!(
+            numbers(
+                &numbering,
+                "[sec A][sec1 B][sec2 C][sec3 D][sec1 E][sec F][sec2 G]"
+            ),
+            some(&["1", "1.a", "1.a.i", "1.a.i.i", "1.b", "2", "2.0.i"])
+        );
+        assert!("".parse::<Numbering>().is_err());
+        assert!("arabic greek".parse::<Numbering>().is_err());
+    }
+
+    #[test]
+    fn arabic_fallback() {
+        let numbering = Numbering {
+            formats: Vec::new(),
+        };
+        assert_eq!(numbering.format(3), NumberFormat::Arabic);
+        assert_eq!(numbers(&numbering, "[sec A][sec1 B]"), some(&["1", "1.1"]));
+    }
+
+    #[test]
+    fn unnumbered_headings() {
+        assert_eq!(
+            numbers(
+                &Numbering::default(),
+                "[sec A][sec{numbered = no; number = 9} B][sec C]"
+            ),
+            vec![Some("1".to_string()), None, Some("2".to_string())]
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let meta = |v: &str| {
+            let mut m = HashMap::new();
+            m.insert("numbering".to_string(), v.to_string());
+            Numbering::from_metadata(&m)
+        };
+        assert_eq!(meta("yes"), Ok(Some(Numbering::default())));
+        assert_eq!(meta("no"), Ok(None));
+        assert_eq!(
+            meta(" Roman arabic ").map(|n| n.unwrap().formats),
+            Ok(vec![NumberFormat::UpperRoman, NumberFormat::Arabic])
+        );
+        assert!(meta("maybe").is_err());
+        assert_eq!(Numbering::from_metadata(&HashMap::new()), Ok(None));
+    }
+
+    #[test]
+    fn sequential() {
+        let mut nodes = parse("[b{id = x} a][b{numbered = no} b][i [b c]]".to_string())
+            .unwrap()
+            .nodes;
+        let count = number_sequentially(&mut nodes, |c| c.cmd == "b", "b");
+        assert_eq!(count, 2);
+        let attrs = |n: &ASTNode| match n {
+            ASTNode::CommandCall(c) => c.attributes.clone(),
+            _ => Vec::new(),
+        };
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+        assert_eq!(attrs(&nodes[0]), vec![pair("id", "x"), pair("number", "1")]);
+        assert!(attrs(&nodes[1]).is_empty());
+        let nested = match &nodes[2] {
+            ASTNode::CommandCall(c) => c.arguments.iter().map(attrs).find(|a| !a.is_empty()),
+            _ => None,
+        };
+        assert_eq!(nested, Some(vec![pair("number", "2"), pair("id", "b-2")]));
+    }
+}
@@ -0,0 +1,155 @@
+//! The outline of a document, built from its headings
+//!
+//! Headings with `{toc = no}` are left out.
+//! The outline is used for `[toc]` (optionally limited with `{depth = n}`)
+//! and by backends that offer it to templates.
+//! The titles are copies without footnotes, index markers and ids (see `repeatable_nodes`).
+
+use crate::slug::is_heading;
+use crate::{repeatable_nodes, ASTNode, BasicCommandType, Command};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    /// The `n` of `Section(n)`
+    pub level: usize,
+    pub id: Option<String>,
+    /// The section number, if the document is numbered
+    pub number: Option<String>,
+    pub title: Vec<ASTNode>,
+    /// The headings that follow this one and have a higher level, up to the next one of the same level
+    pub children: Vec<OutlineEntry>,
+}
+
+impl OutlineEntry {
+    fn from_heading(cmd: &Command) -> Self {
+        let level = match cmd.cmd.parse() {
+            Ok(BasicCommandType::Section(n)) => n,
+            _ => 0,
+        };
+        Self {
+            level,
+            id: cmd.attribute("id").map(str::to_string),
+            number: cmd.attribute("number").map(str::to_string),
+            title: repeatable_nodes(&cmd.arguments),
+            children: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outline {
+    pub entries: Vec<OutlineEntry>,
+}
+
+impl Outline {
+    /// Collects the headings in the nodes (including nested ones), in document order
+    pub fn from_nodes(nodes: &[ASTNode]) -> Self {
+        let mut res = Self::default();
+        res.collect(nodes);
+        res
+    }
+
+    fn collect(&mut self, nodes: &[ASTNode]) {
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if is_heading(c) {
+                    if c.flag("toc") != Some(false) {
+                        self.push(OutlineEntry::from_heading(c));
+                    }
+                } else {
+                    self.collect(&c.arguments);
+                }
+            }
+        }
+    }
+
+    /// Adds the entry after all others, nesting it under the last one with a lower level
+    pub fn push(&mut self, entry: OutlineEntry) {
+        let mut entries = &mut self.entries;
+        loop {
+            match entries.last() {
+                Some(last) if last.level < entry.level => {
+                    entries = &mut entries.last_mut().unwrap().children
+                }
+                _ => break,
+            }
+        }
+        entries.push(entry);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Keeps only the first `depth` levels of nesting
+    pub fn truncate(&mut self, depth: usize) {
+        fn truncate_entries(entries: &mut Vec<OutlineEntry>, depth: usize) {
+            if depth == 0 {
+                entries.clear();
+            }
+            for e in entries {
+                truncate_entries(&mut e.children, depth - 1);
+            }
+        }
+        truncate_entries(&mut self.entries, depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outline, OutlineEntry};
+    use crate::{parse, ASTNode};
+
+    fn outline(source: &str) -> Outline {
+        Outline::from_nodes(&parse(source.to_string()).unwrap().nodes)
+    }
+
+    /// The entries as `title(children)`
+    fn shape(entries: &[OutlineEntry]) -> String {
+        entries
+            .iter()
+            .map(|e| {
+                let title = e.title.iter().map(ASTNode::plain_text).collect::<String>();
+                if e.children.is_empty() {
+                    title
+                } else {
+                    format!("{}({})", title, shape(&e.children))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn nesting() {
+        let o = outline("[sec1 A][sec2 B][sec3 C][sec2 D][sec1 E][sec3 F]");
+        assert_eq!(shape(&o.entries), "A(B(C) D) E(F)");
+        assert_eq!(o.entries[1].children[0].level, 3);
+    }
+
+    #[test]
+    fn excluded_headings() {
+        let o = outline("[sec1 A][sec2{toc = no} B][div [sec2 C]][sec2{toc = yes} D]");
+        assert_eq!(shape(&o.entries), "A(C D)");
+    }
+
+    #[test]
+    fn titles() {
+        let o = outline("[sec1{id = a; number = 1} A[fn note] [i b]]");
+        let e = &o.entries[0];
+        assert_eq!(shape(&o.entries), "A b");
+        assert_eq!(e.id.as_deref(), Some("a"));
+        assert_eq!(e.number.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn truncation() {
+        let mut o = outline("[sec1 A][sec2 B][sec3 C][sec1 D]");
+        o.truncate(2);
+        assert_eq!(shape(&o.entries), "A(B) D");
+        o.truncate(1);
+        assert_eq!(shape(&o.entries), "A D");
+        o.truncate(0);
+        assert!(o.is_empty());
+    }
+}
@@ -14,13 +14,66 @@ mod pipeline {
     use std::collections::HashMap;
     use tlib::iter_tools::AutoEscape;
 
+    /// Metadata keys whose values are collected when they are set again, other keys are overwritten
+    const MULTI_LINE_KEYS: &[&str] = &["link", "style", "symbols"];
+
+    /// Whether a `#` after the character is part of a name, like in the `sec#intro` shorthand
+    fn continues_name(c: char) -> bool {
+        c.is_alphanumeric() || "_-.@".contains(c)
+    }
+
+    /// Finds the unescaped `#` that starts a comment
+    ///
+    /// A `#` directly after a name (like in `[sec#intro ...]`) doesn't start one.
+    /// If `spaced`, the `#` also has to be followed by whitespace or the end of the line
+    fn comment_start(line: &str, spaced: bool) -> Option<usize> {
+        let mut escaped = false;
+        let mut prev = None;
+        let mut iter = line.char_indices().peekable();
+        while let Some((i, c)) = iter.next() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '#' if !prev.is_some_and(continues_name)
+                    && (!spaced || iter.peek().is_none_or(|&(_, c)| c.is_whitespace())) =>
+                {
+                    return Some(i)
+                }
+                _ => (),
+            }
+            prev = Some(c);
+        }
+        None
+    }
+
+    /// Joins lines ending in an escaped line-feed, keeping the source line (1-based) of each
+    fn join_escaped_lines(s: &str) -> Vec<(usize, String)> {
+        let mut res: Vec<(usize, String)> = Vec::new();
+        let mut continued = false;
+        for (i, line) in s.split('\n').enumerate() {
+            match res.last_mut() {
+                Some((_, last)) if continued => last.push_str(line),
+                _ => res.push((i + 1, line.to_string())),
+            }
+            let last = &mut res.last_mut().unwrap().1;
+            continued = last.ends_with('\\');
+            if continued {
+                last.pop();
+            }
+        }
+        res
+    }
+
     /// Extracts comments and metadata as well as deleting escaped line-feeds
+    ///
+    /// Also returns the source line of every line of the result
     // todo: proper error handling
-    pub fn preprocess(s: String) -> (HashMap<String, String>, String) {
-        let mut meta = HashMap::new();
+    pub fn preprocess(s: String) -> (HashMap<String, String>, String, Vec<usize>) {
+        let mut meta: HashMap<String, String> = HashMap::new();
         let mut res = Vec::new();
-        let s = s.replace("\\\n", "");
-        for line in s.split('\n') {
+        let mut lines = Vec::new();
+        for (n, line) in join_escaped_lines(&s) {
+            let line = line.as_str();
             if line.starts_with('!') {
                 let i = line
                     .char_indices()
@@ -32,21 +85,32 @@ mod pipeline {
                     .find(|(ix, c)| ix > &i && !c.is_whitespace())
                     .expect("Found Metadata without value")
                     .0;
-                meta.insert(line[1..i].to_string(), line[i1..].to_string());
-            } else if line.contains('#') {
-                let mut was_escaped = true;
-                let line_iter = line
-                    .split('#')
-                    .take_while(|sl| std::mem::replace(&mut was_escaped, sl.ends_with('\\')))
-                    .collect::<String>();
-                if !line_iter.is_empty() {
-                    res.push(line_iter);
+                let key = &line[1..i];
+                let value = &line[i1..];
+                match meta.get_mut(key) {
+                    // note: these keys are collected, one value per line
+                    Some(v) if MULTI_LINE_KEYS.contains(&key) => {
+                        v.push('\n');
+                        v.push_str(value)
+                    }
+                    _ => {
+                        meta.insert(key.to_string(), value.to_string());
+                    }
+                }
+            } else if let Some(i) = comment_start(
+                line,
+                meta.get("comments").map(String::as_str) == Some("spaced"),
+            ) {
+                if i > 0 {
+                    res.push(line[..i].to_string());
+                    lines.push(n);
                 }
             } else {
                 res.push(line.to_string());
+                lines.push(n);
             }
         }
-        (meta, res.join("\n"))
+        (meta, res.join("\n"), lines)
     }
 
     /// Transforms single line function calls into proper ones
@@ -66,11 +130,24 @@ mod pipeline {
     // note: vvv idk why but the IDE doesn't detect the very CLEAR AND UNAMBIGUOUS `use tlib::iter_tools::AutoEscape;` at the top
     // noinspection RsUnresolvedReference
     /// Containerizes the input, respecting escape characters
-    fn parse_step1(s: String) -> Vec<Containerized<String>> {
-        let mut iter = s.chars().auto_escape(|&c| c == '\\').peekable();
+    ///
+    /// The positions of the containers are their source lines, given the source line of every line of `s`
+    fn parse_step1(s: String, lines: &[usize]) -> Vec<Containerized<String>> {
+        let items = s.chars().auto_escape(|&c| c == '\\').collect::<Vec<_>>();
+        let mut line = 0;
+        let item_lines = items
+            .iter()
+            .map(|&(_, c)| {
+                let res = lines.get(line).copied().unwrap_or(line + 1);
+                if c == '\n' {
+                    line += 1;
+                }
+                res
+            })
+            .collect::<Vec<_>>();
 
         containerize(
-            &mut iter,
+            &mut items.into_iter().peekable(),
             |&(esc, c)| !esc && c == '[',
             |&(esc, c)| !esc && c == ']',
         )
@@ -81,6 +158,7 @@ mod pipeline {
                     .flat_map(|(esc, c)| if esc { vec!['\\', c] } else { vec![c] })
                     .collect::<String>()
             })
+            .map_positions(&mut |i| item_lines[i])
         })
         .collect()
     }
@@ -89,16 +167,18 @@ mod pipeline {
         v.into_iter()
             .map(|c| match c {
                 Containerized::Free(s) => Ok(ASTNode::Text(s)),
-                Containerized::Contained(v) => {
+                Containerized::Contained(v, line) => {
                     let f = parse_command(v)?;
-                    Ok(ASTNode::CommandCall(f(parse_step2)?))
+                    let mut cmd = f(parse_step2)?;
+                    cmd.line = Some(line);
+                    Ok(ASTNode::CommandCall(cmd))
                 }
             })
             .collect()
     }
 
-    pub fn parse_desugared(s: String) -> Result<Vec<ASTNode>, ParseCommandErr> {
-        parse_step2(parse_step1(s))
+    pub fn parse_desugared(s: String, lines: &[usize]) -> Result<Vec<ASTNode>, ParseCommandErr> {
+        parse_step2(parse_step1(s, lines))
     }
 }
 
@@ -124,7 +204,7 @@ mod pipeline {
 
 // note: vvv idk why but the IDE doesn't detect the very CLEAR AND UNAMBIGUOUS `use tlib::iter_tools::{IterSplit, SplitNotEscapedString};` at the top
 // noinspection RsUnresolvedReference
-fn parse_attrs(s: &str) -> Vec<(String, String)> {
+pub(crate) fn parse_attrs(s: &str) -> Vec<(String, String)> {
     s.split_not_escaped::<Vec<_>>(';', '\\', false)
         .into_iter()
         // split_unescaped_string(s, ';', None, false, false)
@@ -148,6 +228,76 @@ fn parse_attrs(s: &str) -> Vec<(String, String)> {
 pub enum ParseCommandErr {
     EmptyBody,
     CommandIsNotIdentifier,
+    /// A command was given two different ids (via `#id` and/or an `id` attribute)
+    ConflictingIds,
+}
+
+fn is_shorthand_start(c: char) -> bool {
+    c == '.' || c == '#'
+}
+
+/// Splits `name.class1.class2#id` into its parts
+///
+/// Backend specific `tag.<name>` commands are namespaced (e.g. `html@tag.div`),
+/// so for those the first `.` is part of the name. An escaped `\\#` works like `#`,
+/// as an unescaped one starts a comment unless `!comments spaced` is set.
+pub(crate) fn parse_shorthand(
+    s: &str,
+    backend_specific: bool,
+) -> Result<(String, Vec<String>, Option<String>), ParseCommandErr> {
+    let s = &s.replace("\\#", "#");
+    let mut name_end = s.find(is_shorthand_start).unwrap_or(s.len());
+    if backend_specific && s.starts_with("tag.") {
+        name_end = s[name_end + 1..]
+            .find(is_shorthand_start)
+            .map_or(s.len(), |i| name_end + 1 + i);
+    }
+
+    let mut classes = Vec::new();
+    let mut id = None;
+    let mut rest = &s[name_end..];
+    while let Some(c) = rest.chars().next() {
+        let end = rest[1..]
+            .find(is_shorthand_start)
+            .map_or(rest.len(), |i| i + 1);
+        let part = &rest[1..end];
+        if !part.is_empty() {
+            if c == '.' {
+                classes.push(part.to_string());
+            } else if id.replace(part.to_string()).is_some() {
+                return Err(ParseCommandErr::ConflictingIds);
+            }
+        }
+        rest = &rest[end..];
+    }
+
+    Ok((s[..name_end].to_string(), classes, id))
+}
+
+/// Merges classes and id from the shorthand syntax into the attributes
+///
+/// The shorthand classes come before the explicit ones
+fn merge_shorthand(
+    attrs: &mut Vec<(String, String)>,
+    classes: Vec<String>,
+    id: Option<String>,
+) -> Result<(), ParseCommandErr> {
+    if !classes.is_empty() {
+        let classes = classes.join(" ");
+        match attrs.iter_mut().find(|(k, _)| k == "class") {
+            Some((_, v)) if !v.trim().is_empty() => *v = format!("{} {}", classes, v),
+            Some((_, v)) => *v = classes,
+            None => attrs.insert(0, ("class".to_string(), classes)),
+        }
+    }
+    if let Some(id) = id {
+        match attrs.iter().find(|(k, _)| k == "id") {
+            Some((_, v)) if *v != id => return Err(ParseCommandErr::ConflictingIds),
+            Some(_) => (),
+            None => attrs.insert(0, ("id".to_string(), id)),
+        }
+    }
+    Ok(())
 }
 
 // note: vvv idk why but the IDE doesn't detect the very CLEAR AND UNAMBIGUOUS `use tlib::iter_tools::{IterSplit, SplitNotEscapedString};` at the top
@@ -166,7 +316,7 @@ pub fn parse_command<
 
     let first = match v.remove(0) {
         Containerized::Free(s) => s,
-        Containerized::Contained(_) => return Err(ParseCommandErr::CommandIsNotIdentifier),
+        Containerized::Contained(..) => return Err(ParseCommandErr::CommandIsNotIdentifier),
     };
 
     // let mut spl = split_unescaped_string(&first, '{', Some(2), false, true);
@@ -190,9 +340,11 @@ pub fn parse_command<
     }
 
     let mut spl = cmd_raw_2.splitn(2, '@').collect::<Vec<_>>();
-    let cmd = spl.pop().unwrap().to_string();
+    let cmd_raw_3 = spl.pop().unwrap();
     let backend = spl.pop().map(|s| s.to_string());
 
+    let (cmd, classes, id) = parse_shorthand(cmd_raw_3, backend.is_some())?;
+
     // section: parse attributes
     // dbgs!(backend, cmd, v);
 
@@ -223,7 +375,7 @@ pub fn parse_command<
                         }
                         attr_string.push_str(&s);
                     }
-                    c @ Containerized::Contained(_) => {
+                    c @ Containerized::Contained(..) => {
                         let s = c.join("[", "]");
                         attr_string.extend(s);
                     }
@@ -235,6 +387,8 @@ pub fn parse_command<
         _ => (),
     }
 
+    merge_shorthand(&mut attrs, classes, id)?;
+
     let iter = put_before
         .into_iter()
         .map(Containerized::Free)
@@ -248,9 +402,17 @@ pub fn parse_command<
 
     let spl = iter
         .flat_map(|c| match c {
-            Containerized::Free(s) => str_split_keep_sep(&s, |&c| c == ';')
+            Containerized::Free(s) => {
+                // escapes are still in the text, an escaped `;` doesn't separate arguments
+                let mut escaped = false;
+                str_split_keep_sep(&s, |&c| {
+                    let is_sep = !escaped && c == ';';
+                    escaped = !escaped && c == '\\';
+                    is_sep
+                })
                 .map(Containerized::Free)
-                .collect::<Vec<_>>(),
+                .collect::<Vec<_>>()
+            }
             c => vec![c],
         })
         .split(
@@ -262,17 +424,25 @@ pub fn parse_command<
         )
         .collect::<Vec<_>>();
 
-    Ok(move |f: F| {
+    Ok(move |mut f: F| {
         let args = spl
             .into_iter()
-            .map(|mut v: Vec<Containerized<String>>| {
+            .enumerate()
+            .map(|(i, mut v): (usize, Vec<Containerized<String>>)| {
+                let mut ws = String::new();
                 if let Some(Containerized::Free(s)) = v.first_mut() {
-                    // note: this is to allow users to opt into having whitespace at the start of args
-                    *s = s.trim_start().replace("\\ ", "")
+                    let trimmed = s.trim_start();
+                    ws.push_str(&s[..s.len() - trimmed.len()]);
+                    // note: a leading `\ ` allows users to opt into having whitespace at the start of args
+                    *s = trimmed.strip_prefix("\\ ").unwrap_or(trimmed).to_string()
                 }
-                v
+                let mut nodes = f(v)?;
+                // the whitespace before the first argument only separates it from the command name
+                if i > 0 {
+                    nodes.insert(0, ASTNode::ArgSeparator(ws));
+                }
+                Ok(nodes)
             })
-            .map(f)
             .collect::<Result<Vec<_>, _>>()?
             .into_iter()
             .flatten()
@@ -282,6 +452,7 @@ pub fn parse_command<
             cmd,
             attributes: attrs,
             arguments: args,
+            line: None,
         })
     })
 }
@@ -289,11 +460,11 @@ pub fn parse_command<
 #[inline]
 pub fn parse(s: String) -> Result<AST, ParseCommandErr> {
     use self::pipeline::*;
-    let (meta, cont) = preprocess(s);
+    let (meta, cont, lines) = preprocess(s);
     let desugared = desugar_slfcalls(cont);
     Ok(AST {
         metadata: meta,
-        nodes: parse_desugared(desugared)?,
+        nodes: parse_desugared(desugared, &lines)?,
     })
 }
 
@@ -301,3 +472,199 @@ pub fn parse(s: String) -> Result<AST, ParseCommandErr> {
 pub fn get_metadata(s: String) -> HashMap<String, String> {
     self::pipeline::preprocess(s).0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, ParseCommandErr};
+    use crate::{ASTNode, Command};
+
+    fn text(s: &str) -> ASTNode {
+        ASTNode::Text(s.to_string())
+    }
+
+    fn sep(ws: &str) -> ASTNode {
+        ASTNode::ArgSeparator(ws.to_string())
+    }
+
+    fn cmd(name: &str, arguments: Vec<ASTNode>) -> ASTNode {
+        ASTNode::CommandCall(Command {
+            backend: None,
+            cmd: name.to_string(),
+            attributes: Vec::new(),
+            arguments,
+            line: Some(1),
+        })
+    }
+
+    fn nodes(s: &str) -> Vec<ASTNode> {
+        parse(s.to_string()).unwrap().nodes
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(
+            nodes("[b foo;bar;baz]"),
+            vec![cmd(
+                "b",
+                vec![text("foo"), sep(""), text("bar"), sep(""), text("baz")]
+            )]
+        );
+        assert_eq!(
+            nodes("[b ;foo]"),
+            vec![cmd("b", vec![text(""), sep(""), text("foo")])]
+        );
+    }
+
+    #[test]
+    fn whitespace_around_separators() {
+        // whitespace after the name is dropped, before a `;` it belongs to the argument
+        assert_eq!(
+            nodes("[b   foo ;  bar]"),
+            vec![cmd("b", vec![text("foo "), sep("  "), text("bar")])]
+        );
+        assert_eq!(
+            nodes("[b foo;\n\tbar]"),
+            vec![cmd("b", vec![text("foo"), sep("\n\t"), text("bar")])]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(nodes("a \\[b\\] \\; c"), vec![text("a \\[b\\] \\; c")]);
+        assert_eq!(
+            nodes("[b foo\\;bar]"),
+            vec![cmd("b", vec![text("foo\\;bar")])]
+        );
+        // only a leading escaped space opts into leading whitespace
+        assert_eq!(
+            nodes("[b foo; \\  bar\\ baz]"),
+            vec![cmd("b", vec![text("foo"), sep(" "), text(" bar\\ baz")])]
+        );
+        assert_eq!(nodes("a\\\nb"), vec![text("ab")]);
+    }
+
+    #[test]
+    fn nested_commands() {
+        assert_eq!(
+            nodes("x [b foo [i bar; baz]; qux] y"),
+            vec![
+                text("x "),
+                cmd(
+                    "b",
+                    vec![
+                        text("foo "),
+                        cmd("i", vec![text("bar"), sep(" "), text("baz")]),
+                        text(""),
+                        sep(" "),
+                        text("qux"),
+                    ]
+                ),
+                text(" y"),
+            ]
+        );
+        assert_eq!(
+            nodes("un[i believ]able"),
+            vec![text("un"), cmd("i", vec![text("believ")]), text("able")]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            nodes("[b x]#f\na # b\n#c\nd#e"),
+            vec![cmd("b", vec![text("x")]), text("\na \nd#e")]
+        );
+        assert_eq!(nodes("a \\# b"), vec![text("a \\# b")]);
+        assert_eq!(
+            nodes("!comments spaced\na # b\nc#d\n#"),
+            vec![text("a \nc#d")]
+        );
+    }
+
+    #[test]
+    fn shorthand() {
+        assert_eq!(
+            super::parse_shorthand("sec.note#intro", false),
+            Ok((
+                "sec".to_string(),
+                vec!["note".to_string()],
+                Some("intro".to_string())
+            ))
+        );
+        assert_eq!(
+            super::parse_shorthand("tag.div.note", true),
+            Ok(("tag.div".to_string(), vec!["note".to_string()], None))
+        );
+        assert_eq!(
+            super::parse_shorthand("br.note", true),
+            Ok(("br".to_string(), vec!["note".to_string()], None))
+        );
+    }
+
+    #[test]
+    fn shorthand_commands() {
+        let attributes = |s: &str| match parse(s.to_string()).map(|ast| ast.nodes) {
+            Ok(nodes) => match &nodes[..] {
+                [ASTNode::CommandCall(c)] => Ok((c.cmd.clone(), c.attributes.clone())),
+                nodes => panic!("expected one command, got {:?}", nodes),
+            },
+            Err(e) => Err(e),
+        };
+        let attrs = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            attributes("[sec#intro x]"),
+            Ok(("sec".to_string(), attrs(&[("id", "intro")])))
+        );
+        assert_eq!(
+            attributes("[sec.note.wide#intro{class = big} x]"),
+            Ok((
+                "sec".to_string(),
+                attrs(&[("id", "intro"), ("class", "note wide big")])
+            ))
+        );
+        assert_eq!(
+            attributes("[sec#intro{id = intro} x]"),
+            Ok(("sec".to_string(), attrs(&[("id", "intro")])))
+        );
+        assert_eq!(
+            attributes("[sec#intro{id = other} x]"),
+            Err(ParseCommandErr::ConflictingIds)
+        );
+        assert_eq!(
+            attributes("[sec#a#b x]"),
+            Err(ParseCommandErr::ConflictingIds)
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let lines = |s: &str| {
+            nodes(s)
+                .into_iter()
+                .filter_map(|n| match n {
+                    ASTNode::CommandCall(c) => c.line,
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines("[b x]\n!title t\n# comment\n[i y] [i z]"),
+            vec![1, 4, 4]
+        );
+        assert_eq!(lines("a \\\nb\n.b x\n[i\ny]"), vec![3, 4]);
+    }
+
+    #[test]
+    fn metadata() {
+        let meta =
+            parse("!title a\n!style b{color = red}\n!title b\n!style i{color = blue}".to_string())
+                .unwrap()
+                .metadata;
+        assert_eq!(meta["title"], "b");
+        assert_eq!(meta["style"], "b{color = red}\ni{color = blue}");
+    }
+}
@@ -152,15 +152,16 @@ pub use tlib::iter_tools::{
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Containerized<T> {
     Free(T),
-    Contained(Vec<Self>),
+    /// The contents and the position of the left delimiter
+    Contained(Vec<Self>, usize),
 }
 
 impl<T> Containerized<T> {
     fn map_inner<U, F: FnMut(T) -> U>(self, f: &mut F) -> Containerized<U> {
         match self {
             Containerized::Free(t) => Containerized::Free(f(t)),
-            Containerized::Contained(v) => {
-                Containerized::Contained(v.into_iter().map(|c| c.map_inner(&mut *f)).collect())
+            Containerized::Contained(v, pos) => {
+                Containerized::Contained(v.into_iter().map(|c| c.map_inner(&mut *f)).collect(), pos)
             }
         }
     }
@@ -169,6 +170,17 @@ impl<T> Containerized<T> {
         self.map_inner(&mut f)
     }
 
+    /// Replaces the positions of the containers, e.g. to turn indices into line numbers
+    pub fn map_positions<F: FnMut(usize) -> usize>(self, f: &mut F) -> Self {
+        match self {
+            Containerized::Free(t) => Containerized::Free(t),
+            Containerized::Contained(v, pos) => {
+                let pos = f(pos);
+                Containerized::Contained(v.into_iter().map(|c| c.map_positions(f)).collect(), pos)
+            }
+        }
+    }
+
     // fn flat_map_inner<I: IntoIterator, F: FnMut(T) -> I>(
     //     self,
     //     f: &mut F,
@@ -193,7 +205,7 @@ impl<T> Containerized<T> {
     pub fn join<U: Into<T> + Clone>(self, left: U, right: U) -> Vec<T> {
         match self {
             Containerized::Free(t) => vec![t],
-            Containerized::Contained(v) => {
+            Containerized::Contained(v, _) => {
                 let mut res = vec![left.clone().into()];
                 res.append(
                     &mut v
@@ -208,25 +220,31 @@ impl<T> Containerized<T> {
     }
 }
 
+/// The position of a container and what it holds so far
+type OpenContainer<T> = (usize, Vec<Containerized<Vec<T>>>);
+
+/// Groups the items between matching delimiters,
+/// the position of a container is the index of its left delimiter in `iter`
 pub fn containerize<I: Iterator>(
     iter: &mut Peekable<I>,
     mut left: impl FnMut(&I::Item) -> bool,
     mut right: impl FnMut(&I::Item) -> bool,
 ) -> Vec<Containerized<Vec<I::Item>>> {
-    let mut stack: Vec<Vec<Containerized<Vec<I::Item>>>> = vec![vec![]];
+    let mut stack: Vec<OpenContainer<I::Item>> = vec![(0, vec![])];
 
-    for t in iter {
+    for (i, t) in iter.enumerate() {
         if left(&t) {
-            stack.push(Vec::new());
+            stack.push((i, Vec::new()));
         } else if right(&t) {
             // todo: proper error handling
-            let v = stack.pop().unwrap();
+            let (pos, v) = stack.pop().unwrap();
             stack
                 .last_mut()
                 .expect("Unmatched right delimeter")
-                .push(Containerized::Contained(v));
+                .1
+                .push(Containerized::Contained(v, pos));
         } else {
-            let last = stack.last_mut().unwrap();
+            let last = &mut stack.last_mut().unwrap().1;
             if let Some(Containerized::Free(v)) = last.last_mut() {
                 v.push(t);
             } else {
@@ -239,7 +257,7 @@ pub fn containerize<I: Iterator>(
         panic!("Unmatched left delimeter");
     }
 
-    stack.pop().unwrap()
+    stack.pop().unwrap().1
 }
 //
 // pub struct AutoEscape<I, F> {
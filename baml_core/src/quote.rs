@@ -0,0 +1,153 @@
+//! Block quotes
+//!
+//! `[quote{author = ...; source = ...} ...]` quotes a passage, both attributes are optional.
+//! `source` names the work the passage is from (a book, a talk, ...).
+//! `build_quotes` turns the commands into `ASTNode::Quote`s for the backends.
+
+use crate::{trim_nodes, ASTNode, BasicCommandType, Command};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Quote {
+    pub author: Option<String>,
+    pub source: Option<String>,
+    pub content: Vec<ASTNode>,
+    /// The other attributes of the command, like `class` or style attributes
+    pub attributes: Vec<(String, String)>,
+    /// The source line of the command
+    pub line: Option<usize>,
+}
+
+impl Quote {
+    /// Takes the attribution and the content out of a quote command
+    pub fn from_command(cmd: Command) -> Self {
+        let attribute = |key| {
+            cmd.attribute(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let author = attribute("author");
+        let source = attribute("source");
+        let mut attributes = cmd.attributes;
+        attributes.retain(|(k, _)| k != "author" && k != "source");
+        Self {
+            author,
+            source,
+            content: trim_nodes(cmd.arguments),
+            attributes,
+            line: cmd.line,
+        }
+    }
+
+    /// Turns the quote back into a quote command, the attribution becomes attributes again
+    pub fn into_command(self) -> Command {
+        let mut attributes = Vec::new();
+        attributes.extend(self.author.map(|a| ("author".to_string(), a)));
+        attributes.extend(self.source.map(|s| ("source".to_string(), s)));
+        attributes.extend(self.attributes);
+        Command {
+            backend: None,
+            cmd: BasicCommandType::Quote.to_string(),
+            attributes,
+            arguments: self.content,
+            line: self.line,
+        }
+    }
+
+    /// Whether there is an author or a source
+    pub fn has_attribution(&self) -> bool {
+        self.author.is_some() || self.source.is_some()
+    }
+}
+
+pub fn is_quote(cmd: &Command) -> bool {
+    cmd.backend.is_none() && cmd.cmd.parse() == Ok(BasicCommandType::Quote)
+}
+
+/// Replaces the quote commands (also nested ones) with `ASTNode::Quote`s
+///
+/// The other transforms only look into commands, so this runs after them.
+pub fn build_quotes(nodes: &mut [ASTNode]) {
+    for node in nodes {
+        match node {
+            ASTNode::CommandCall(c) if is_quote(c) => {
+                if let ASTNode::CommandCall(cmd) =
+                    std::mem::replace(node, ASTNode::Text(String::new()))
+                {
+                    let mut quote = Quote::from_command(cmd);
+                    build_quotes(&mut quote.content);
+                    *node = ASTNode::Quote(quote);
+                }
+            }
+            ASTNode::CommandCall(c) => build_quotes(&mut c.arguments),
+            ASTNode::Quote(q) => build_quotes(&mut q.content),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_quotes;
+    use crate::{parse, ASTNode, Backend, Command, AST};
+
+    /// A backend that only implements the required methods
+    struct Plain;
+
+    impl Backend for Plain {
+        type Rendered = String;
+        type Output = String;
+
+        fn backend_id() -> &'static str {
+            "plain"
+        }
+
+        fn emit_text(&mut self, text: String) -> String {
+            text
+        }
+
+        fn run_command(&mut self, cmd: Command) -> Option<String> {
+            let attributes = cmd
+                .attributes
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(",");
+            let content = cmd
+                .arguments
+                .into_iter()
+                .filter_map(|n| self.handle_node(n))
+                .collect::<String>();
+            Some(format!("{}({}){{{}}}", cmd.cmd, attributes, content))
+        }
+
+        fn compile_ast(&mut self, ast: AST) -> String {
+            ast.nodes
+                .into_iter()
+                .filter_map(|n| self.handle_node(n))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn nested_quotes() {
+        let mut nodes = parse("[quote{author = A; class = x} a [quote b]]".to_string())
+            .unwrap()
+            .nodes;
+        build_quotes(&mut nodes);
+        let quote = match &nodes[0] {
+            ASTNode::Quote(q) => q,
+            node => panic!("expected a quote, got {:?}", node),
+        };
+        assert_eq!(quote.author.as_deref(), Some("A"));
+        assert_eq!(quote.attributes, vec![("class".into(), "x".into())]);
+        assert!(matches!(&quote.content[1], ASTNode::Quote(q) if q.author.is_none()));
+    }
+
+    #[test]
+    fn default_rendering() {
+        let mut ast = parse("[quote{source = S; class = x} a [b b]]".to_string()).unwrap();
+        build_quotes(&mut ast.nodes);
+        assert!(matches!(ast.nodes[0], ASTNode::Quote(_)));
+        assert_eq!(Plain.compile_ast(ast), "quote(source=S,class=x){a b(){b}}");
+    }
+}
@@ -0,0 +1,253 @@
+//! Labels and cross-references
+//!
+//! `[label name]` inside a section heading, figure, table or equation names it,
+//! and `[ref name]` anywhere in the document becomes a link to it.
+//! The link text is the number of the target if it has one and its title otherwise.
+//! Labels have to be collected before references can be resolved,
+//! so this runs after headings have their ids and numbers.
+
+use crate::slug::{is_heading, Slugger};
+use crate::{repeatable_nodes, ASTNode, BasicCommandType, Command};
+use std::collections::HashMap;
+
+/// Commands besides headings that can be labeled
+const LABELED_COMMANDS: &[&str] = &["equation", "fig", "figure", "table"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefTarget {
+    pub id: String,
+    pub number: Option<String>,
+    /// The text of a heading, empty for other targets
+    pub title: Vec<ASTNode>,
+}
+
+fn is_labeled_command(cmd: &Command) -> bool {
+    is_heading(cmd) || (cmd.backend.is_none() && LABELED_COMMANDS.contains(&cmd.cmd.as_str()))
+}
+
+fn is_command(node: &ASTNode, name: &str) -> bool {
+    matches!(node, ASTNode::CommandCall(c) if c.backend.is_none() && c.cmd == name)
+}
+
+/// Removes the labels from the nodes, except those belonging to nested targets, and returns their names
+fn take_labels(nodes: &mut Vec<ASTNode>) -> Vec<String> {
+    let mut res = Vec::new();
+    nodes.retain(|node| {
+        if is_command(node, "label") {
+            res.push(node.plain_text().trim().to_string());
+            return false;
+        }
+        true
+    });
+    for node in nodes {
+        if let ASTNode::CommandCall(c) = node {
+            if !is_labeled_command(c) {
+                res.append(&mut take_labels(&mut c.arguments));
+            }
+        }
+    }
+    res
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Labels {
+    pub targets: HashMap<String, RefTarget>,
+    /// Creates the ids of targets without one, avoiding the ids in the document
+    slugger: Slugger,
+}
+
+impl Labels {
+    fn add(&mut self, cmd: &mut Command, names: Vec<String>, errors: &mut Vec<String>) {
+        for name in names {
+            if name.is_empty() {
+                errors.push(format!("empty label in `{}`", cmd.cmd));
+                continue;
+            }
+            if self.targets.contains_key(&name) {
+                errors.push(format!("duplicate label `{}`", name));
+                continue;
+            }
+            let id = match cmd.attribute("id") {
+                Some(id) => id.to_string(),
+                None => {
+                    let id = self.slugger.slug(&name);
+                    cmd.attributes.push(("id".to_string(), id.clone()));
+                    id
+                }
+            };
+            let mut title = if is_heading(cmd) {
+                repeatable_nodes(&cmd.arguments)
+            } else {
+                Vec::new()
+            };
+            // the label usually comes last, after a space
+            if let Some(ASTNode::Text(s)) = title.last_mut() {
+                *s = s.trim_end().to_string();
+            }
+            let target = RefTarget {
+                id,
+                number: cmd.attribute("number").map(str::to_string),
+                title,
+            };
+            self.targets.insert(name, target);
+        }
+    }
+
+    /// Collects all labels and removes them from the document, returns errors for misplaced or duplicate ones
+    ///
+    /// Targets without an id get one based on the label name.
+    pub fn collect(&mut self, nodes: &mut Vec<ASTNode>) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.slugger.reserve_all(nodes);
+        self.collect_inner(nodes, &mut errors);
+        errors
+    }
+
+    fn collect_inner(&mut self, nodes: &mut Vec<ASTNode>, errors: &mut Vec<String>) {
+        nodes.retain(|node| {
+            if is_command(node, "label") {
+                errors.push(format!(
+                    "label `{}` is not part of a section, figure, table or equation",
+                    node.plain_text().trim()
+                ));
+                return false;
+            }
+            true
+        });
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if is_labeled_command(c) {
+                    let names = take_labels(&mut c.arguments);
+                    self.add(c, names, errors);
+                }
+                self.collect_inner(&mut c.arguments, errors);
+            }
+        }
+    }
+
+    /// Replaces every `[ref name]` with a link to the target, returns errors for undefined labels
+    ///
+    /// Undefined references are replaced by `??`
+    pub fn resolve(&self, nodes: &mut [ASTNode]) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.resolve_inner(nodes, &mut errors);
+        errors
+    }
+
+    fn resolve_inner(&self, nodes: &mut [ASTNode], errors: &mut Vec<String>) {
+        for node in nodes {
+            if !is_command(node, "ref") {
+                if let ASTNode::CommandCall(c) = node {
+                    self.resolve_inner(&mut c.arguments, errors);
+                }
+                continue;
+            }
+            let name = node.plain_text().trim().to_string();
+            let target = match self.targets.get(&name) {
+                Some(t) => t,
+                None => {
+                    errors.push(format!("reference to undefined label `{}`", name));
+                    *node = ASTNode::Text("??".to_string());
+                    continue;
+                }
+            };
+            let mut arguments = vec![
+                ASTNode::Text(format!("#{}", target.id)),
+                ASTNode::ArgSeparator(String::new()),
+            ];
+            match &target.number {
+                Some(n) => arguments.push(ASTNode::Text(n.clone())),
+                None if !target.title.is_empty() => arguments.extend(target.title.iter().cloned()),
+                None => arguments.push(ASTNode::Text(name)),
+            }
+            let (attributes, line) = match node {
+                ASTNode::CommandCall(c) => (std::mem::take(&mut c.attributes), c.line),
+                _ => unreachable!(),
+            };
+            *node = ASTNode::CommandCall(Command {
+                backend: None,
+                cmd: BasicCommandType::Link.to_string(),
+                attributes,
+                arguments,
+                line,
+            });
+        }
+    }
+}
+
+/// Collects the labels of the document and resolves the references to them, returns the errors
+pub fn resolve_refs(nodes: &mut Vec<ASTNode>) -> Vec<String> {
+    let mut labels = Labels::default();
+    let mut errors = labels.collect(nodes);
+    errors.append(&mut labels.resolve(nodes));
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_refs, Labels};
+    use crate::{parse, split_first_argument, ASTNode};
+
+    fn nodes(source: &str) -> Vec<ASTNode> {
+        parse(source.to_string()).unwrap().nodes
+    }
+
+    /// The resolved references as `target|text`
+    fn links(nodes: &[ASTNode]) -> Vec<String> {
+        let mut res = Vec::new();
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if c.cmd == "link" {
+                    let (target, text) = split_first_argument(c.arguments.clone());
+                    let plain =
+                        |v: Vec<ASTNode>| v.iter().map(ASTNode::plain_text).collect::<String>();
+                    res.push(format!("{}|{}", plain(target), plain(text)));
+                } else {
+                    res.append(&mut links(&c.arguments));
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn targets() {
+        let mut nodes = nodes(
+            "[sec{number = 2} Intro [label intro]][sec Other[fn x] [label other]]\
+             [fig{number = 1} [label fig]][equation{id = e} x [label eq]]\
+             [ref intro] [ref other] [ref fig] [b [ref eq]]",
+        );
+        let errors = resolve_refs(&mut nodes);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            links(&nodes),
+            vec!["#intro|2", "#other|Other", "#fig|1", "#e|eq"]
+        );
+    }
+
+    #[test]
+    fn explicit_ids_are_avoided() {
+        let mut nodes = nodes("[sec{id = a} A][sec B [label a]][ref a]");
+        assert!(resolve_refs(&mut nodes).is_empty());
+        assert_eq!(links(&nodes), vec!["#a-1|B"]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut doc = nodes("[b [label lost]][sec A [label a]][sec B [label a]] [ref nope]");
+        assert_eq!(
+            resolve_refs(&mut doc),
+            vec![
+                "label `lost` is not part of a section, figure, table or equation".to_string(),
+                "duplicate label `a`".to_string(),
+                "reference to undefined label `nope`".to_string(),
+            ]
+        );
+        assert_eq!(doc.last(), Some(&ASTNode::Text("??".to_string())));
+        let mut labels = Labels::default();
+        assert_eq!(
+            labels.collect(&mut nodes("[sec A [label  ]]")),
+            vec!["empty label in `sec`".to_string()]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Emoji and symbol shortcodes
+//!
+//! `:name:` in text and `[sym name]` are replaced with the symbol of that name,
+//! e.g. `:tada:` with 🎉 and `[sym copyright]` with ©. Unknown names in text are left alone,
+//! so that things like times (`10:30:00`) are safe. Extra symbols (which can replace built-in ones)
+//! are written as `<name> = <symbol>`, one per line, in a file or in the `!symbols` metadata.
+
+use crate::{ASTNode, BasicCommandType};
+use std::collections::HashMap;
+
+const BUILTIN: &[(&str, &str)] = &[
+    // emoji
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("grin", "😁"),
+    ("laughing", "😆"),
+    ("joy", "😂"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("thinking", "🤔"),
+    ("confused", "😕"),
+    ("cry", "😢"),
+    ("sob", "😭"),
+    ("heart", "❤️"),
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("-1", "👎"),
+    ("thumbsdown", "👎"),
+    ("ok_hand", "👌"),
+    ("clap", "👏"),
+    ("wave", "👋"),
+    ("pray", "🙏"),
+    ("eyes", "👀"),
+    ("tada", "🎉"),
+    ("rocket", "🚀"),
+    ("fire", "🔥"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("zap", "⚡"),
+    ("bulb", "💡"),
+    ("warning", "⚠️"),
+    ("construction", "🚧"),
+    ("bug", "🐛"),
+    ("lock", "🔒"),
+    ("key", "🔑"),
+    ("memo", "📝"),
+    ("book", "📖"),
+    ("email", "📧"),
+    ("calendar", "📅"),
+    ("coffee", "☕"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("question", "❓"),
+    ("exclamation", "❗"),
+    // typographic symbols
+    ("copyright", "©"),
+    ("registered", "®"),
+    ("tm", "™"),
+    ("section", "§"),
+    ("para", "¶"),
+    ("dagger", "†"),
+    ("ddagger", "‡"),
+    ("bullet", "•"),
+    ("middot", "·"),
+    ("ellipsis", "…"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("degree", "°"),
+    ("permille", "‰"),
+    ("euro", "€"),
+    ("pound", "£"),
+    ("yen", "¥"),
+    ("cent", "¢"),
+    ("half", "½"),
+    ("third", "⅓"),
+    ("quarter", "¼"),
+    ("three_quarters", "¾"),
+    ("check", "✓"),
+    ("cross", "✗"),
+    // arrows
+    ("arrow_left", "←"),
+    ("arrow_right", "→"),
+    ("arrow_up", "↑"),
+    ("arrow_down", "↓"),
+    ("arrow_left_right", "↔"),
+    ("arrow_up_down", "↕"),
+    ("implies", "⇒"),
+    ("implied_by", "⇐"),
+    ("iff", "⇔"),
+    ("mapsto", "↦"),
+    ("return", "↩"),
+    // math symbols
+    ("plusminus", "±"),
+    ("minus", "−"),
+    ("times", "×"),
+    ("divide", "÷"),
+    ("neq", "≠"),
+    ("leq", "≤"),
+    ("geq", "≥"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("infinity", "∞"),
+    ("sum", "∑"),
+    ("product", "∏"),
+    ("sqrt", "√"),
+    ("integral", "∫"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("subset", "⊂"),
+    ("superset", "⊃"),
+    ("union", "∪"),
+    ("intersection", "∩"),
+    ("emptyset", "∅"),
+    ("and", "∧"),
+    ("or", "∨"),
+    ("not", "¬"),
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("pi", "π"),
+    ("sigma", "σ"),
+    ("omega", "ω"),
+];
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+-".contains(c)
+}
+
+/// The symbol table, the built-in symbols plus extra ones
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Shortcodes {
+    pub extra: HashMap<String, String>,
+}
+
+impl Shortcodes {
+    /// Parses one `<name> = <symbol>` per line, skipping empty lines and `# ` comments
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut extra = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line == "#" || line.starts_with("# ") {
+                continue;
+            }
+            let (name, symbol) = line
+                .split_once('=')
+                .ok_or_else(|| format!("symbol without `=` on line {}", i + 1))?;
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(is_name_char) {
+                return Err(format!("invalid symbol name on line {}", i + 1));
+            }
+            extra.insert(name.to_string(), symbol.trim().to_string());
+        }
+        Ok(Self { extra })
+    }
+
+    /// The symbols from the `!symbols` metadata of a document
+    pub fn from_metadata(meta: &HashMap<String, String>) -> Result<Self, String> {
+        match meta.get("symbols") {
+            Some(s) => Self::parse(s).map_err(|e| format!("{} of `!symbols`", e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Adds the symbols of `other`, replacing own ones with the same name
+    pub fn extend(&mut self, other: Shortcodes) {
+        self.extra.extend(other.extra);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.extra.get(name).map(String::as_str).or_else(|| {
+            BUILTIN
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, symbol)| *symbol)
+        })
+    }
+
+    /// Replaces the known `:name:` shortcodes in text, escaped colons (`\:`) don't start one
+    fn expand_text(&self, s: &str) -> String {
+        let mut res = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(i) = rest.find(':') {
+            let after = &rest[i + 1..];
+            let len = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            let symbol = if !rest[..i].ends_with('\\') && after[len..].starts_with(':') {
+                self.get(&after[..len])
+            } else {
+                None
+            };
+            match symbol {
+                Some(symbol) => {
+                    res.push_str(&rest[..i]);
+                    res.push_str(symbol);
+                    rest = &after[len + 1..];
+                }
+                None => {
+                    res.push_str(&rest[..=i]);
+                    rest = after;
+                }
+            }
+        }
+        res.push_str(rest);
+        res
+    }
+
+    fn expand_inner(&self, nodes: &mut [ASTNode], warnings: &mut Vec<String>) {
+        for node in nodes {
+            match node {
+                ASTNode::Text(s) => *s = self.expand_text(s),
+                ASTNode::CommandCall(c)
+                    if c.backend.is_none() && c.cmd.parse() == Ok(BasicCommandType::Symbol) =>
+                {
+                    let name = c.plain_text().trim().to_string();
+                    *node = ASTNode::Text(match self.get(&name) {
+                        Some(symbol) => symbol.to_string(),
+                        None => {
+                            warnings.push(format!("unknown symbol `{}`", name));
+                            name
+                        }
+                    });
+                }
+                ASTNode::CommandCall(c) if c.is_verbatim() => (),
+                ASTNode::CommandCall(c) => {
+                    let start = c.prose_start();
+                    self.expand_inner(&mut c.arguments[start..], warnings);
+                }
+                ASTNode::Quote(q) => self.expand_inner(&mut q.content, warnings),
+                ASTNode::ArgSeparator(_) => (),
+            }
+        }
+    }
+
+    /// Replaces the shortcodes and `[sym]` commands, returns warnings for unknown `[sym]` names
+    ///
+    /// A `[sym]` with an unknown name is replaced with the name.    ///
+    /// Verbatim commands and arguments that are not prose (see `Command::prose_start`) are left alone.
+    pub fn expand(&self, nodes: &mut [ASTNode]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        self.expand_inner(nodes, &mut warnings);
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shortcodes;
+    use crate::{parse, ASTNode};
+    use std::collections::HashMap;
+
+    fn expand(shortcodes: &Shortcodes, source: &str) -> (String, Vec<String>) {
+        let mut nodes = parse(source.to_string()).unwrap().nodes;
+        let warnings = shortcodes.expand(&mut nodes);
+        (nodes.iter().map(ASTNode::plain_text).collect(), warnings)
+    }
+
+    #[test]
+    fn builtins() {
+        let shortcodes = Shortcodes::default();
+        assert_eq!(
+            expand(
+                &shortcodes,
+                r"[sym copyright] 2024 :tada: at 10:30:00 \:tada: :nope:"
+            ),
+            (
+                r"© 2024 🎉 at 10:30:00 \:tada: :nope:".to_string(),
+                Vec::new()
+            )
+        );
+        assert_eq!(
+            expand(
+                &shortcodes,
+                "[code :tada:][link https://a.com/:tada:; :tada:]"
+            )
+            .0,
+            ":tada:https://a.com/:tada: 🎉"
+        );
+    }
+
+    #[test]
+    fn extra_symbols() {
+        let mut meta = HashMap::new();
+        meta.insert(
+            "symbols".to_string(),
+            "# party\ntada = 🥳\nsmiley_cat = 😺".to_string(),
+        );
+        let mut shortcodes = Shortcodes::parse("tada = !").unwrap();
+        shortcodes.extend(Shortcodes::from_metadata(&meta).unwrap());
+        assert_eq!(
+            expand(&shortcodes, ":tada: [sym smiley_cat] :tm:").0,
+            "🥳 😺 ™"
+        );
+        meta.insert("symbols".to_string(), "a".to_string());
+        assert_eq!(
+            Shortcodes::from_metadata(&meta),
+            Err("symbol without `=` on line 1 of `!symbols`".to_string())
+        );
+        assert!(Shortcodes::parse("a b = c").is_err());
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(
+            expand(&Shortcodes::default(), "a [sym nope] b"),
+            (
+                "a nope b".to_string(),
+                vec!["unknown symbol `nope`".to_string()]
+            )
+        );
+    }
+}
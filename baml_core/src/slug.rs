@@ -0,0 +1,148 @@
+//! Stable ids for headings, derived from their text
+
+use crate::{repeatable_nodes, ASTNode, BasicCommandType, Command};
+use std::collections::HashSet;
+
+/// Lowercases the text and joins its words with `-`, dropping punctuation
+///
+/// Letters and digits of any script are kept, so `Über Maße` becomes `über-maße`
+pub fn slugify(s: &str) -> String {
+    let mut res = String::new();
+    let mut dash = false;
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            if dash && !res.is_empty() {
+                res.push('-');
+            }
+            dash = false;
+            res.extend(c.to_lowercase());
+        } else if c.is_whitespace() || c == '-' || c == '_' {
+            dash = true;
+        }
+    }
+    res
+}
+
+/// Creates slugs that are unique within a document
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Slugger {
+    used: HashSet<String>,
+}
+
+impl Slugger {
+    /// Marks an id as taken, e.g. because it was given explicitly
+    pub fn reserve(&mut self, id: &str) {
+        self.used.insert(id.to_string());
+    }
+
+    /// Slugifies the text, appending `-1`, `-2`, ... if the slug is already taken
+    pub fn slug(&mut self, s: &str) -> String {
+        let mut base = slugify(s);
+        if base.is_empty() {
+            base = "section".to_string();
+        }
+        let mut res = base.clone();
+        let mut n = 0;
+        while self.used.contains(&res) {
+            n += 1;
+            res = format!("{}-{}", base, n);
+        }
+        self.used.insert(res.clone());
+        res
+    }
+
+    /// Reserves all explicit ids in the nodes
+    pub fn reserve_all(&mut self, nodes: &[ASTNode]) {
+        for node in nodes {
+            if let ASTNode::CommandCall(c) = node {
+                if let Some((_, id)) = c.attributes.iter().find(|(k, _)| k == "id") {
+                    self.reserve(id);
+                }
+                self.reserve_all(&c.arguments);
+            }
+        }
+    }
+}
+
+/// Whether the command is one of the generic `sec` commands
+pub fn is_heading(cmd: &Command) -> bool {
+    cmd.backend.is_none() && matches!(cmd.cmd.parse(), Ok(BasicCommandType::Section(_)))
+}
+
+/// The plain text of a heading, leaving out footnotes, index markers and label names
+fn heading_text(nodes: &[ASTNode]) -> String {
+    repeatable_nodes(nodes)
+        .iter()
+        .map(ASTNode::plain_text)
+        .collect()
+}
+
+fn assign_ids(nodes: &mut [ASTNode], slugger: &mut Slugger) {
+    for node in nodes {
+        if let ASTNode::CommandCall(c) = node {
+            if is_heading(c) && !c.attributes.iter().any(|(k, _)| k == "id") {
+                let id = slugger.slug(&heading_text(&c.arguments));
+                c.attributes.push(("id".to_string(), id));
+            }
+            assign_ids(&mut c.arguments, slugger);
+        }
+    }
+}
+
+/// Gives every heading without an explicit `id` attribute one based on its text
+///
+/// The ids are unique within the nodes, taking explicit ids into account
+pub fn assign_heading_ids(nodes: &mut [ASTNode]) {
+    let mut slugger = Slugger::default();
+    slugger.reserve_all(nodes);
+    assign_ids(nodes, &mut slugger);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign_heading_ids, slugify, Slugger};
+    use crate::{parse, ASTNode};
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(
+            slugify("  snake_case and -dashes- "),
+            "snake-case-and-dashes"
+        );
+        assert_eq!(slugify("Über Maße 2"), "über-maße-2");
+        assert_eq!(slugify("?!"), "");
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut slugger = Slugger::default();
+        slugger.reserve("intro-1");
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("intro"), "intro-2");
+        assert_eq!(slugger.slug("Intro!"), "intro-3");
+        assert_eq!(slugger.slug("..."), "section");
+        assert_eq!(slugger.slug(""), "section-1");
+    }
+
+    #[test]
+    fn heading_ids() {
+        let mut nodes = parse(
+            "[sec1 Intro][sec2{id = intro-1} Other][sec2 Intro[fn note]][b Intro]".to_string(),
+        )
+        .unwrap()
+        .nodes;
+        assign_heading_ids(&mut nodes);
+        let ids = nodes
+            .iter()
+            .map(|n| match n {
+                ASTNode::CommandCall(c) => c.attribute("id"),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![Some("intro"), Some("intro-1"), Some("intro-2"), None]
+        );
+    }
+}
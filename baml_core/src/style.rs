@@ -0,0 +1,354 @@
+//! Portable style attributes
+//!
+//! These are understood by every backend, which translates them into its own idiom
+//! (e.g. css for html). On the commands of the language (like `sec` or `b`),
+//! they can be written as-is (`{font-size = 18pt}`). Everywhere else they have to be
+//! namespaced (`{style.font-size = 18pt}`), since generic commands become tags of the backend
+//! and keys like `align` may be attributes of those.
+//! Only namespaced keys and near misses of style keys (like `font-sise` on a `sec`) are reported
+//! when they are unknown, since any other attribute might be meant for the backend.
+//! Backend specific commands (like `html@tag.div`) have no style attributes,
+//! their attributes are passed on as they are.
+
+use crate::values::{parse_value, Color, Length, ValueError};
+use crate::{BasicCommandType, Command};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StyleKey {
+    FontSize,
+    Color,
+    Align,
+    Weight,
+    Margin,
+}
+
+impl FromStr for StyleKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::StyleKey::*;
+        Ok(match s {
+            "font-size" => FontSize,
+            "color" => Color,
+            "align" => Align,
+            "weight" => Weight,
+            "margin" => Margin,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl ToString for StyleKey {
+    fn to_string(&self) -> String {
+        use self::StyleKey::*;
+        match self {
+            FontSize => "font-size",
+            Color => "color",
+            Align => "align",
+            Weight => "weight",
+            Margin => "margin",
+        }
+        .to_string()
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1)
+                .min(row[j] + 1)
+                .min(diag + usize::from(ca != cb));
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+impl StyleKey {
+    const ALL: [StyleKey; 5] = [
+        StyleKey::FontSize,
+        StyleKey::Color,
+        StyleKey::Align,
+        StyleKey::Weight,
+        StyleKey::Margin,
+    ];
+
+    /// The style key that an unknown key is probably a typo of
+    ///
+    /// Typos rarely change the first letter, so keys like `height` (for `weight`) are not suggested.
+    pub fn suggest(key: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .map(|&k| (k, k.to_string()))
+            .filter(|(_, name)| key.chars().next() == name.chars().next())
+            .map(|(k, name)| (edit_distance(key, &name), k))
+            .filter(|&(d, _)| d <= 2)
+            .min_by_key(|&(d, _)| d)
+            .map(|(_, k)| k)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+impl FromStr for Align {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::Align::*;
+        Ok(match s {
+            "left" => Left,
+            "right" => Right,
+            "center" | "centre" => Center,
+            "justify" => Justify,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl ToString for Align {
+    fn to_string(&self) -> String {
+        use self::Align::*;
+        match self {
+            Left => "left",
+            Right => "right",
+            Center => "center",
+            Justify => "justify",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Weight {
+    Light,
+    Normal,
+    Bold,
+    /// A numeric weight from 1 to 1000, where 400 is normal and 700 is bold
+    Numeric(u16),
+}
+
+impl FromStr for Weight {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::Weight::*;
+        Ok(match s {
+            "light" => Light,
+            "normal" => Normal,
+            "bold" => Bold,
+            s => match s.parse() {
+                Ok(n) if (1..=1000).contains(&n) => Numeric(n),
+                _ => return Err(()),
+            },
+        })
+    }
+}
+
+impl ToString for Weight {
+    fn to_string(&self) -> String {
+        use self::Weight::*;
+        match self {
+            Light => "light",
+            Normal => "normal",
+            Bold => "bold",
+            Numeric(n) => return n.to_string(),
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub font_size: Option<Length>,
+    pub color: Option<Color>,
+    pub align: Option<Align>,
+    pub weight: Option<Weight>,
+    pub margin: Option<Length>,
+}
+
+impl Style {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn set(&mut self, cmd: &str, key: StyleKey, value: &str) -> Result<(), ValueError> {
+        let k = key.to_string();
+        match key {
+            StyleKey::FontSize => self.font_size = Some(parse_value(cmd, &k, value, "a length")?),
+            StyleKey::Color => self.color = Some(parse_value(cmd, &k, value, "a color")?),
+            StyleKey::Align => {
+                self.align = Some(parse_value(
+                    cmd,
+                    &k,
+                    value,
+                    "`left`, `right`, `center` or `justify`",
+                )?)
+            }
+            StyleKey::Weight => {
+                self.weight = Some(parse_value(
+                    cmd,
+                    &k,
+                    value,
+                    "`light`, `normal`, `bold` or a number from 1 to 1000",
+                )?)
+            }
+            StyleKey::Margin => self.margin = Some(parse_value(cmd, &k, value, "a length")?),
+        }
+        Ok(())
+    }
+
+    /// Whether the attribute key is one of the style keys, namespaced or not
+    pub fn is_style_key(key: &str) -> bool {
+        key.starts_with("style.") || key.parse::<StyleKey>().is_ok()
+    }
+
+    /// Removes all style attributes from the command and collects them
+    ///
+    /// Also returns warnings about unknown style keys and errors for invalid values,
+    /// which are left out while the other keys still apply.
+    /// Keys without the `style.` namespace only count on the commands of the language,
+    /// backend specific commands are always left unchanged.
+    pub fn extract(cmd: &mut Command) -> (Self, Vec<String>, Vec<ValueError>) {
+        let mut res = Self::default();
+        let mut warnings = Vec::new();
+        let mut errors = Vec::new();
+        if cmd.backend.is_some() {
+            return (res, warnings, errors);
+        }
+        let name = cmd.cmd.clone();
+        let line = cmd.line;
+        let known = name.parse::<BasicCommandType>().is_ok();
+        cmd.attributes.retain(|(k, v)| {
+            let (namespaced, key) = match k.strip_prefix("style.") {
+                Some(key) => (true, key),
+                None if known => (false, k.as_str()),
+                None => return true,
+            };
+            match key.parse::<StyleKey>() {
+                Ok(key) => {
+                    if let Err(e) = res.set(&name, key, v) {
+                        errors.push(e.at_line(line));
+                    }
+                    false
+                }
+                Err(()) if namespaced => {
+                    warnings.push(format!("unknown style `{}` ignored (in `{}`)", key, name));
+                    false
+                }
+                Err(()) => {
+                    if let Some(suggestion) = StyleKey::suggest(key) {
+                        warnings.push(format!(
+                            "unknown attribute `{}` (in `{}`), did you mean `{}`?",
+                            key,
+                            name,
+                            suggestion.to_string()
+                        ))
+                    }
+                    true
+                }
+            }
+        });
+        (res, warnings, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Align, Style, StyleKey, Weight};
+    use crate::values::Length;
+    use crate::Command;
+
+    fn command(backend: Option<&str>, name: &str, attributes: &[(&str, &str)]) -> Command {
+        Command {
+            backend: backend.map(str::to_string),
+            cmd: name.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            arguments: Vec::new(),
+            line: Some(3),
+        }
+    }
+
+    fn keys(cmd: &Command) -> Vec<&str> {
+        cmd.attributes.iter().map(|(k, _)| k.as_str()).collect()
+    }
+
+    #[test]
+    fn known_commands() {
+        let mut cmd = command(
+            None,
+            "sec",
+            &[
+                ("font-size", "18pt"),
+                ("style.weight", "bold"),
+                ("align", "center"),
+                ("class", "note"),
+            ],
+        );
+        let (style, warnings, errors) = Style::extract(&mut cmd);
+        assert_eq!(style.font_size, "18pt".parse::<Length>().ok());
+        assert_eq!(style.weight, Some(Weight::Bold));
+        assert_eq!(style.align, Some(Align::Center));
+        assert!(warnings.is_empty() && errors.is_empty());
+        assert_eq!(keys(&cmd), vec!["class"]);
+    }
+
+    #[test]
+    fn generic_commands() {
+        let mut cmd = command(None, "div", &[("align", "center"), ("style.color", "red")]);
+        let (style, warnings, _) = Style::extract(&mut cmd);
+        assert_eq!(style.align, None);
+        assert!(style.color.is_some());
+        assert!(warnings.is_empty());
+        assert_eq!(keys(&cmd), vec!["align"]);
+
+        let mut cmd = command(Some("html"), "tag.div", &[("style.color", "red")]);
+        assert!(Style::extract(&mut cmd).0.is_empty());
+        assert_eq!(keys(&cmd), vec!["style.color"]);
+    }
+
+    #[test]
+    fn invalid_values() {
+        let mut cmd = command(None, "b", &[("color", "nope"), ("weight", "light")]);
+        let (style, _, errors) = Style::extract(&mut cmd);
+        assert_eq!(style.weight, Some(Weight::Light));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, Some(3));
+        assert!(keys(&cmd).is_empty());
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(StyleKey::suggest("font-sise"), Some(StyleKey::FontSize));
+        assert_eq!(StyleKey::suggest("colour"), Some(StyleKey::Color));
+        assert_eq!(StyleKey::suggest("margins"), Some(StyleKey::Margin));
+        assert_eq!(StyleKey::suggest("height"), None);
+        assert_eq!(StyleKey::suggest("alt"), None);
+        assert_eq!(StyleKey::suggest("href"), None);
+
+        let mut cmd = command(None, "sec", &[("colour", "red"), ("style.colour", "red")]);
+        let (_, warnings, _) = Style::extract(&mut cmd);
+        assert_eq!(
+            warnings,
+            vec![
+                "unknown attribute `colour` (in `sec`), did you mean `color`?".to_string(),
+                "unknown style `colour` ignored (in `sec`)".to_string(),
+            ]
+        );
+        assert_eq!(keys(&cmd), vec!["colour"]);
+    }
+}
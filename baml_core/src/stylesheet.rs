@@ -0,0 +1,243 @@
+//! Rules that give matching commands default attributes
+//!
+//! A rule is written as `<selector>: <key> = <value>; ...`, one rule per line,
+//! e.g. `!style sec: font-size = 18pt` in a document.
+//! Selectors use the command shorthand syntax (`sec`, `sec.note`, `.note`, `#intro`, `html@tag.div`).
+//! Attributes given in a call always override the rules, later rules override earlier ones
+//! and classes are merged instead of overridden.
+
+use crate::parser::{parse_attrs, parse_shorthand};
+use crate::{ASTNode, Command};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Selector {
+    pub backend: Option<String>,
+    /// `None` matches every command
+    pub cmd: Option<String>,
+    pub classes: Vec<String>,
+    pub id: Option<String>,
+}
+
+impl Selector {
+    pub fn matches(&self, cmd: &Command) -> bool {
+        let attr = |key: &str| {
+            cmd.attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        self.backend == cmd.backend
+            && self.cmd.as_ref().is_none_or(|c| *c == cmd.cmd)
+            && self.id.as_deref().is_none_or(|id| attr("id") == Some(id))
+            && self.classes.iter().all(|c| {
+                attr("class")
+                    .unwrap_or("")
+                    .split_whitespace()
+                    .any(|x| x == c)
+            })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(());
+        }
+        let mut spl = s.splitn(2, '@').collect::<Vec<_>>();
+        let rest = spl.pop().unwrap();
+        let backend = spl.pop().map(|s| s.to_string());
+        let (cmd, classes, id) = parse_shorthand(rest, backend.is_some()).map_err(|_| ())?;
+        Ok(Self {
+            backend,
+            cmd: if cmd.is_empty() { None } else { Some(cmd) },
+            classes,
+            id,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StyleRule {
+    pub selector: Selector,
+    pub attributes: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StyleSheet {
+    pub rules: Vec<StyleRule>,
+}
+
+impl StyleSheet {
+    /// Parses one rule per line, skipping empty lines and `# ` comments
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line == "#" || line.starts_with("# ") {
+                continue;
+            }
+            let colon = line
+                .find(':')
+                .ok_or_else(|| format!("style rule without `:` on line {}", i + 1))?;
+            let selector = line[..colon]
+                .parse()
+                .map_err(|_| format!("invalid selector on line {}", i + 1))?;
+            rules.push(StyleRule {
+                selector,
+                attributes: parse_attrs(&line[colon + 1..]),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// The rules from the `!style` metadata of a document
+    pub fn from_metadata(meta: &HashMap<String, String>) -> Result<Self, String> {
+        match meta.get("style") {
+            Some(s) => Self::parse(s).map_err(|e| format!("{} of `!style`", e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Adds the rules of `other` after the own ones, so they take precedence
+    pub fn extend(&mut self, other: StyleSheet) {
+        self.rules.extend(other.rules);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn apply_to_command(&self, cmd: &mut Command) {
+        let mut defaults: Vec<(String, String)> = Vec::new();
+        for rule in self.rules.iter().filter(|r| r.selector.matches(cmd)) {
+            for (k, v) in &rule.attributes {
+                match defaults.iter_mut().find(|(dk, _)| dk == k) {
+                    Some((_, dv)) if k == "class" => *dv = format!("{} {}", dv, v),
+                    Some((_, dv)) => *dv = v.clone(),
+                    None => defaults.push((k.clone(), v.clone())),
+                }
+            }
+        }
+        for (k, v) in defaults {
+            match cmd.attributes.iter_mut().find(|(ck, _)| *ck == k) {
+                Some((_, cv)) if k == "class" => *cv = format!("{} {}", v, cv),
+                Some(_) => (),
+                None => cmd.attributes.push((k, v)),
+            }
+        }
+    }
+
+    /// Adds the default attributes to all matching commands, including nested ones
+    pub fn apply(&self, nodes: &mut [ASTNode]) {
+        if self.is_empty() {
+            return;
+        }
+        for node in nodes {
+            if let ASTNode::CommandCall(cmd) = node {
+                self.apply_to_command(cmd);
+                self.apply(&mut cmd.arguments);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Selector, StyleSheet};
+    use crate::{parse, ASTNode, Command};
+
+    fn commands(source: &str, sheet: &str) -> Vec<Command> {
+        let mut nodes = parse(source.to_string()).unwrap().nodes;
+        StyleSheet::parse(sheet).unwrap().apply(&mut nodes);
+        nodes
+            .into_iter()
+            .filter_map(|n| match n {
+                ASTNode::CommandCall(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn attrs(v: &[(&str, &str)]) -> Vec<(String, String)> {
+        v.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn selectors() {
+        let cmd = match &parse("[sec.note.wide#intro x]".to_string()).unwrap().nodes[0] {
+            ASTNode::CommandCall(c) => c.clone(),
+            _ => unreachable!(),
+        };
+        let matches = |s: &str| s.parse::<Selector>().unwrap().matches(&cmd);
+        assert!(matches("sec"));
+        assert!(matches(".note"));
+        assert!(matches("sec.wide.note"));
+        assert!(matches("#intro"));
+        assert!(matches("sec.note#intro"));
+        assert!(!matches("b"));
+        assert!(!matches(".other"));
+        assert!(!matches("#outro"));
+        assert!(!matches("html@sec"));
+        assert!("a b".parse::<Selector>().is_err());
+        assert!("".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        let sheet = "sec: font-size = 18pt; color = red\n\
+                     # a comment\n\
+                     .note: color = blue; class = boxed";
+        let cmds = commands("[sec x][sec.note{color = green} y][sec.note z][b w]", sheet);
+        assert_eq!(
+            cmds[0].attributes,
+            attrs(&[("font-size", "18pt"), ("color", "red")])
+        );
+        // attributes in the call win over all rules, classes are merged
+        assert_eq!(
+            cmds[1].attributes,
+            attrs(&[
+                ("class", "boxed note"),
+                ("color", "green"),
+                ("font-size", "18pt")
+            ])
+        );
+        // later rules win over earlier ones
+        assert_eq!(
+            cmds[2].attributes,
+            attrs(&[
+                ("class", "boxed note"),
+                ("font-size", "18pt"),
+                ("color", "blue")
+            ])
+        );
+        assert!(cmds[3].attributes.is_empty());
+    }
+
+    #[test]
+    fn nested_commands() {
+        let cmds = commands("[sec a [b x]]", "b: weight = bold");
+        match &cmds[0].arguments[1] {
+            ASTNode::CommandCall(c) => assert_eq!(c.attributes, attrs(&[("weight", "bold")])),
+            n => panic!("expected a command, got {:?}", n),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            StyleSheet::parse("sec font-size = 1pt"),
+            Err("style rule without `:` on line 1".to_string())
+        );
+        assert_eq!(
+            StyleSheet::parse("\na b: x = y"),
+            Err("invalid selector on line 2".to_string())
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Task items
+//!
+//! `[todo ...]` and `[done ...]` are list items with a checkbox, open and finished respectively.
+//! They usually go into a list (`[ul [todo ...] [done ...]]`) and can be nested.
+//! `open_tasks` collects the open ones of several documents, e.g. for a summary of meeting notes.
+
+use crate::{ASTNode, BasicCommandType, Command, AST};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Task {
+    pub done: bool,
+    /// The content without the nested tasks, which are tasks of their own
+    pub content: Vec<ASTNode>,
+}
+
+fn is_task(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::CommandCall(c) if Task::from_command(c).is_some())
+}
+
+/// A copy of the nodes without tasks, commands that held nothing but tasks (like a nested list) are left out too
+fn without_tasks(nodes: &[ASTNode]) -> Vec<ASTNode> {
+    nodes
+        .iter()
+        .filter(|node| !is_task(node))
+        .filter_map(|node| match node {
+            ASTNode::CommandCall(c) => {
+                let arguments = without_tasks(&c.arguments);
+                let only_tasks = c.arguments.iter().any(is_task)
+                    && arguments.iter().all(|n| n.plain_text().trim().is_empty());
+                if only_tasks {
+                    return None;
+                }
+                Some(ASTNode::CommandCall(Command {
+                    arguments,
+                    ..c.clone()
+                }))
+            }
+            node => Some(node.clone()),
+        })
+        .collect()
+}
+
+impl Task {
+    /// Reads a task command, `None` for other commands
+    pub fn from_command(cmd: &Command) -> Option<Self> {
+        if cmd.backend.is_some() {
+            return None;
+        }
+        match cmd.cmd.parse() {
+            Ok(BasicCommandType::Task(done)) => Some(Self {
+                done,
+                content: without_tasks(&cmd.arguments),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The text of the task, without any markup and with whitespace collapsed
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .map(ASTNode::plain_text)
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn collect_inner(nodes: &[ASTNode], res: &mut Vec<Task>) {
+    for node in nodes {
+        match node {
+            ASTNode::CommandCall(c) => {
+                res.extend(Task::from_command(c));
+                collect_inner(&c.arguments, res);
+            }
+            ASTNode::Quote(q) => collect_inner(&q.content, res),
+            _ => (),
+        }
+    }
+}
+
+/// All tasks in the nodes, in document order (an item comes before the items nested in it)
+pub fn collect_tasks(nodes: &[ASTNode]) -> Vec<Task> {
+    let mut res = Vec::new();
+    collect_inner(nodes, &mut res);
+    res
+}
+
+/// The open tasks of several documents, with the position of the document they are in
+pub fn open_tasks<'a, I: IntoIterator<Item = &'a AST>>(documents: I) -> Vec<(usize, Task)> {
+    documents
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, ast)| {
+            collect_tasks(&ast.nodes)
+                .into_iter()
+                .filter(|t| !t.done)
+                .map(move |t| (i, t))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::collect_tasks;
+    use crate::parse;
+
+    #[test]
+    fn nested_tasks() {
+        let nodes = parse("[ul [todo a [ul [done b] [todo c]]] [todo d]]".to_string())
+            .unwrap()
+            .nodes;
+        let tasks = collect_tasks(&nodes);
+        assert_eq!(
+            tasks.iter().map(|t| (t.done, t.text())).collect::<Vec<_>>(),
+            vec![
+                (false, "a".to_string()),
+                (true, "b".to_string()),
+                (false, "c".to_string()),
+                (false, "d".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Smart typography
+//!
+//! Straight quotes become curly ones (in the style of the document's `!lang`),
+//! `--` and `---` become en and em dashes, `...` becomes an ellipsis and the space between
+//! a number and a unit becomes a non-breaking one. French also gets non-breaking spaces before
+//! `;`, `:`, `!` and `?` and inside guillemets. Escaped characters, verbatim commands
+//! and arguments that are not prose (like link targets, see `Command::prose_start`) are left alone.
+
+use crate::ASTNode;
+
+const NBSP: char = '\u{a0}';
+
+/// Units that are kept on the same line as the number before them
+///
+/// Single letters like `m` or `A` are left out, after a number they are too often a word
+/// or a list marker (`Chapter 1 A new start`).
+const UNITS: &[&str] = &[
+    "%", "‰", "°C", "°F", "pt", "px", "mm", "cm", "km", "ft", "mi", "mg", "kg", "ml", "ms", "min",
+    "Hz", "kHz", "MHz", "GHz", "mV", "kV", "mA", "kW", "MW", "kWh", "kJ", "Pa", "kPa", "bar", "kB",
+    "KB", "MB", "GB", "TB", "KiB", "MiB", "GiB", "TiB", "€", "$", "£",
+];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Typography {
+    pub double_quotes: (String, String),
+    pub single_quotes: (String, String),
+    /// Whether `;`, `:`, `!` and `?` are preceded by a (non-breaking) space, like in French
+    pub space_before_punctuation: bool,
+}
+
+impl Default for Typography {
+    fn default() -> Self {
+        Self::for_lang("en")
+    }
+}
+
+fn is_opening_context(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{<-–—/„“‘‚«‹".contains(c),
+    }
+}
+
+impl Typography {
+    /// The conventions of a language given as a tag like `de` or `en-US`, unknown ones get English
+    pub fn for_lang(lang: &str) -> Self {
+        let primary = lang.trim().split(['-', '_']).next().unwrap().to_lowercase();
+        let quotes = |d: (&str, &str), s: (&str, &str)| {
+            (
+                (d.0.to_string(), d.1.to_string()),
+                (s.0.to_string(), s.1.to_string()),
+            )
+        };
+        let (double_quotes, single_quotes) = match primary.as_str() {
+            "de" | "cs" | "sk" | "da" => quotes(("„", "“"), ("‚", "‘")),
+            "fr" => quotes(
+                (&format!("«{}", NBSP), &format!("{}»", NBSP)),
+                (&format!("‹{}", NBSP), &format!("{}›", NBSP)),
+            ),
+            "es" | "it" | "pt" | "ru" => quotes(("«", "»"), ("“", "”")),
+            "nl" | "pl" | "hu" | "ro" => quotes(("„", "”"), ("‚", "’")),
+            "sv" | "fi" => quotes(("”", "”"), ("’", "’")),
+            _ => quotes(("“", "”"), ("‘", "’")),
+        };
+        Self {
+            double_quotes,
+            single_quotes,
+            space_before_punctuation: primary == "fr",
+        }
+    }
+
+    /// Transforms text, `prev` is the character before it (which may be in an earlier node)
+    fn transform(&self, s: &str, prev: &mut Option<char>) -> String {
+        let chars = s.chars().collect::<Vec<_>>();
+        let mut res = String::with_capacity(s.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            let run = chars[i..].iter().take_while(|x| **x == c).count();
+            match c {
+                '\\' => {
+                    res.push(c);
+                    if let Some(n) = next {
+                        res.push(n);
+                        i += 1;
+                    }
+                }
+                // longer runs (like `----`) are kept, they are probably not meant as a dash
+                '-' if run > 3 => {
+                    res.extend(&chars[i..i + run]);
+                    i += run - 1;
+                }
+                '-' if run == 2 || run == 3 => {
+                    res.push(if run == 3 { '—' } else { '–' });
+                    i += run - 1;
+                }
+                '.' if run == 3 => {
+                    res.push('…');
+                    i += 2;
+                }
+                '"' if is_opening_context(*prev) => res.push_str(&self.double_quotes.0),
+                '"' => res.push_str(&self.double_quotes.1),
+                // apostrophes are always ’
+                '\'' if prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric) =>
+                {
+                    res.push('’')
+                }
+                '\'' if is_opening_context(*prev) => res.push_str(&self.single_quotes.0),
+                '\'' => res.push_str(&self.single_quotes.1),
+                ';' | ':' | '!' | '?' if self.space_before_punctuation => {
+                    if res.ends_with(' ') {
+                        res.pop();
+                        res.push(NBSP);
+                    } else if c != ':' && prev.is_some_and(char::is_alphanumeric) {
+                        // a `:` directly after a word is more likely part of a time or url
+                        res.push(NBSP);
+                    }
+                    res.push(c);
+                }
+                c => res.push(c),
+            }
+            *prev = res.chars().last();
+            i += 1;
+        }
+        nbsp_before_units(&res)
+    }
+
+    fn apply_inner(&self, nodes: &mut [ASTNode], prev: &mut Option<char>) {
+        for node in nodes {
+            match node {
+                ASTNode::Text(s) => *s = self.transform(s, prev),
+                ASTNode::ArgSeparator(_) => *prev = Some(' '),
+                ASTNode::Quote(q) => self.apply_inner(&mut q.content, prev),
+                ASTNode::CommandCall(c) if c.is_verbatim() => {
+                    *prev = c.plain_text().chars().last().or(*prev)
+                }
+                ASTNode::CommandCall(c) => {
+                    let start = c.prose_start();
+                    self.apply_inner(&mut c.arguments[start..], prev);
+                }
+            }
+        }
+    }
+
+    pub fn apply(&self, nodes: &mut [ASTNode]) {
+        self.apply_inner(nodes, &mut None);
+    }
+}
+
+/// Replaces the space between a number and a unit with a non-breaking one
+fn nbsp_before_units(s: &str) -> String {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut res = String::with_capacity(s.len());
+    for (i, c) in chars.iter().enumerate() {
+        if *c == ' ' && i > 0 && chars[i - 1].is_ascii_digit() {
+            let rest = chars[i + 1..].iter().collect::<String>();
+            let is_unit = UNITS.iter().any(|unit| {
+                rest.starts_with(unit)
+                    && !rest[unit.len()..]
+                        .chars()
+                        .next()
+                        .is_some_and(char::is_alphanumeric)
+            });
+            if is_unit {
+                res.push(NBSP);
+                continue;
+            }
+        }
+        res.push(*c);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Typography;
+    use crate::{parse, ASTNode};
+
+    fn apply(lang: &str, source: &str) -> Vec<ASTNode> {
+        let mut nodes = parse(source.to_string()).unwrap().nodes;
+        Typography::for_lang(lang).apply(&mut nodes);
+        nodes
+    }
+
+    fn text(lang: &str, source: &str) -> String {
+        apply(lang, source)
+            .iter()
+            .map(ASTNode::plain_text)
+            .collect()
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(text("en", r#""a 'b' c" don't"#), "“a ‘b’ c” don’t");
+        assert_eq!(text("de-AT", r#""a [b 'b'] c""#), "„a ‚b‘ c“");
+        assert_eq!(text("fr", r#""a" b: c !"#), "«\u{a0}a\u{a0}» b: c\u{a0}!");
+    }
+
+    #[test]
+    fn dashes() {
+        assert_eq!(
+            text("en", "1--2 a --- b a-b ---- ..."),
+            "1–2 a — b a-b ---- …"
+        );
+        assert_eq!(text("en", r"a \-- b"), r"a \-- b");
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(
+            text("en", "5 km, 10 % and 3 MB"),
+            "5\u{a0}km, 10\u{a0}% and 3\u{a0}MB"
+        );
+        assert_eq!(
+            text("en", "Chapter 1 A new start, 2 min2"),
+            "Chapter 1 A new start, 2 min2"
+        );
+    }
+
+    #[test]
+    fn verbatim_is_skipped() {
+        let nodes = apply(
+            "en",
+            r#"[code "a" -- b][html@tag.script x--][textarea a--][link "u--rl"; "t"][ref a--b]"#,
+        );
+        let args = nodes
+            .iter()
+            .map(|n| match n {
+                ASTNode::CommandCall(c) => c.arguments.iter().map(ASTNode::plain_text).collect(),
+                n => n.plain_text(),
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(
+            args,
+            [r#""a" -- b"#, "x--", "a--", r#""u--rl" “t”"#, "a--b"]
+        );
+    }
+}
@@ -0,0 +1,682 @@
+//! Typed values for attributes and arguments (lengths, colors, percentages)
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Unit {
+    Pt,
+    Px,
+    Mm,
+    Cm,
+    In,
+    Pc,
+    Em,
+    Ex,
+    Rem,
+    Percent,
+}
+
+impl FromStr for Unit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::Unit::*;
+        Ok(match s {
+            "pt" => Pt,
+            "px" => Px,
+            "mm" => Mm,
+            "cm" => Cm,
+            "in" => In,
+            "pc" => Pc,
+            "em" => Em,
+            "ex" => Ex,
+            "rem" => Rem,
+            "%" => Percent,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl ToString for Unit {
+    fn to_string(&self) -> String {
+        use self::Unit::*;
+        match self {
+            Pt => "pt",
+            Px => "px",
+            Mm => "mm",
+            Cm => "cm",
+            In => "in",
+            Pc => "pc",
+            Em => "em",
+            Ex => "ex",
+            Rem => "rem",
+            Percent => "%",
+        }
+        .to_string()
+    }
+}
+
+impl Unit {
+    /// How many points one of this unit is, `None` for relative units
+    ///
+    /// Pixels are taken to be 1/96 in, as in css
+    pub fn in_points(self) -> Option<f64> {
+        use self::Unit::*;
+        Some(match self {
+            Pt => 1.0,
+            Px => 0.75,
+            Mm => 72.0 / 25.4,
+            Cm => 72.0 / 2.54,
+            In => 72.0,
+            Pc => 12.0,
+            Em | Ex | Rem | Percent => return None,
+        })
+    }
+
+    pub fn is_absolute(self) -> bool {
+        self.in_points().is_some()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Length {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Length {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Self { value, unit }
+    }
+
+    /// Converts between absolute units, `None` if either unit is relative
+    ///
+    /// Converting to the same unit always works
+    pub fn to_unit(self, unit: Unit) -> Option<Self> {
+        if unit == self.unit {
+            return Some(self);
+        }
+        let value = self.value * self.unit.in_points()? / unit.in_points()?;
+        Some(Self { value, unit })
+    }
+
+    pub fn to_pt(self) -> Option<f64> {
+        self.to_unit(Unit::Pt).map(|l| l.value)
+    }
+
+    /// Turns a relative length into an absolute one
+    ///
+    /// `em` and `%` are relative to `font_size`, `rem` to `root_font_size` and `ex` is taken to be half an `em`
+    pub fn resolve(self, font_size: Length, root_font_size: Length) -> Option<Self> {
+        let (factor, base) = match self.unit {
+            Unit::Em => (1.0, font_size),
+            Unit::Ex => (0.5, font_size),
+            Unit::Percent => (0.01, font_size),
+            Unit::Rem => (1.0, root_font_size),
+            _ => return Some(self),
+        };
+        if !base.unit.is_absolute() {
+            return None;
+        }
+        Some(Self::new(self.value * factor * base.value, base.unit))
+    }
+}
+
+impl FromStr for Length {
+    type Err = ();
+
+    /// Parses `<number><unit>`, the unit can only be left out for zero
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let i = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+            .unwrap_or(s.len());
+        let value = s[..i].parse::<f64>().map_err(|_| ())?;
+        if !value.is_finite() {
+            return Err(());
+        }
+        let unit = match s[i..].trim_start() {
+            "" if value == 0.0 => Unit::Pt,
+            unit => unit.parse()?,
+        };
+        Ok(Self { value, unit })
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit.to_string())
+    }
+}
+
+/// A percentage, e.g. `50%`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Percentage(pub f64);
+
+impl Percentage {
+    /// The percentage as a fraction, e.g. `0.5` for `50%`
+    pub fn fraction(self) -> f64 {
+        self.0 / 100.0
+    }
+}
+
+impl FromStr for Percentage {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_suffix('%') {
+            Some(x) => match x.trim_end().parse::<f64>() {
+                Ok(x) if x.is_finite() => Ok(Percentage(x)),
+                _ => Err(()),
+            },
+            None => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Percentage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+/// An sRGB color with alpha
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// The css named colors
+const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+impl Color {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` if the color is not opaque
+    pub fn to_hex(self) -> String {
+        if self.a == 255 {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    fn from_hex(s: &str) -> Result<Self, ()> {
+        if !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(());
+        }
+        let digits = match s.len() {
+            // short forms like `#f00` repeat every digit
+            3 | 4 => s.chars().flat_map(|c| vec![c, c]).collect::<String>(),
+            6 | 8 => s.to_string(),
+            _ => return Err(()),
+        };
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| ());
+        Ok(Self {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if digits.len() == 8 { channel(6)? } else { 255 },
+        })
+    }
+
+    /// Parses the arguments of a color function, `a, b, c[, alpha]` or `a b c[ / alpha]`
+    ///
+    /// Returns the three channels and the alpha, which is a number from 0 to 1 or a percentage
+    fn function_args(s: &str) -> Result<([&str; 3], u8), ()> {
+        let (channels, alpha) = if s.contains(',') {
+            let mut v = s.split(',').map(str::trim).collect::<Vec<_>>();
+            let alpha = if v.len() == 4 { v.pop() } else { None };
+            (v, alpha)
+        } else {
+            let (channels, alpha) = match s.split_once('/') {
+                Some((channels, alpha)) => (channels, Some(alpha.trim())),
+                None => (s, None),
+            };
+            (channels.split_whitespace().collect(), alpha)
+        };
+        let alpha = match alpha {
+            Some(a) => match a.parse::<Percentage>() {
+                Ok(p) => p.fraction(),
+                Err(()) => a.parse::<f64>().map_err(|_| ())?,
+            },
+            None => 1.0,
+        };
+        match channels[..] {
+            [a, b, c] if (0.0..=1.0).contains(&alpha) => {
+                Ok(([a, b, c], (alpha * 255.0).round() as u8))
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Parses the inside of `rgb(...)` or `rgba(...)`, channels are numbers from 0 to 255 or percentages
+    fn from_rgb_args(s: &str) -> Result<Self, ()> {
+        let (channels, a) = Self::function_args(s)?;
+        let channel = |c: &str| {
+            let v = match c.parse::<Percentage>() {
+                Ok(p) => p.fraction() * 255.0,
+                Err(()) => c.parse::<f64>().map_err(|_| ())?,
+            };
+            if (0.0..=255.0).contains(&v) {
+                Ok(v.round() as u8)
+            } else {
+                Err(())
+            }
+        };
+        Ok(Self {
+            r: channel(channels[0])?,
+            g: channel(channels[1])?,
+            b: channel(channels[2])?,
+            a,
+        })
+    }
+
+    /// Parses the inside of `hsl(...)` or `hsla(...)`: hue in degrees, saturation and lightness as percentages
+    fn from_hsl_args(s: &str) -> Result<Self, ()> {
+        let (channels, a) = Self::function_args(s)?;
+        let hue = channels[0].strip_suffix("deg").unwrap_or(channels[0]);
+        let h = hue.parse::<f64>().map_err(|_| ())?;
+        let percentage = |c: &str| match c.parse::<Percentage>() {
+            Ok(p) if (0.0..=100.0).contains(&p.0) => Ok(p.fraction()),
+            _ => Err(()),
+        };
+        let (s, l) = (percentage(channels[1])?, percentage(channels[2])?);
+        if !h.is_finite() {
+            return Err(());
+        }
+
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u8 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        let channel = |v: f64| ((v + m) * 255.0).round() as u8;
+        Ok(Self {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+            a,
+        })
+    }
+}
+
+impl FromStr for Color {
+    type Err = ();
+
+    /// Parses `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, the `rgb`, `rgba`, `hsl` and `hsla` functions
+    /// and the css color names
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            return Self::from_hex(hex);
+        }
+        if let Some((name, args)) = s.strip_suffix(')').and_then(|s| s.split_once('(')) {
+            return match name.trim_end().to_ascii_lowercase().as_str() {
+                "rgb" | "rgba" => Self::from_rgb_args(args.trim()),
+                "hsl" | "hsla" => Self::from_hsl_args(args.trim()),
+                _ => Err(()),
+            };
+        }
+        if s == "transparent" {
+            return Ok(Self {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            });
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, [r, g, b])| Self::rgb(*r, *g, *b))
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+/// A value that failed to parse, together with where it was found
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ValueError {
+    pub command: String,
+    /// The attribute key, or a description like `argument 1`
+    pub key: String,
+    pub value: String,
+    /// What was expected, e.g. `a length`
+    pub expected: &'static str,
+    /// The source line of the command, if known
+    pub line: Option<usize>,
+}
+
+impl ValueError {
+    /// Sets the source line of the error
+    pub fn at_line(mut self, line: Option<usize>) -> Self {
+        self.line = line;
+        self
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid value `{}` for `{}` in command `{}`",
+            self.value, self.key, self.command
+        )?;
+        if let Some(line) = self.line {
+            write!(f, " on line {}", line)?;
+        }
+        write!(f, ", expected {}", self.expected)
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+/// Parses `value`, producing a `ValueError` located at `command` and `key` on failure
+///
+/// The error has no source line, see `ValueError::at_line`
+pub fn parse_value<T: FromStr>(
+    command: &str,
+    key: &str,
+    value: &str,
+    expected: &'static str,
+) -> Result<T, ValueError> {
+    value.parse().map_err(|_| ValueError {
+        command: command.to_string(),
+        key: key.to_string(),
+        value: value.to_string(),
+        expected,
+        line: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_value, Color, Length, Percentage, Unit, ValueError};
+
+    fn color(s: &str) -> Result<String, ()> {
+        s.parse::<Color>().map(Color::to_hex)
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(color("#f00"), Ok("#ff0000".to_string()));
+        assert_eq!(color("RebeccaPurple"), Ok("#663399".to_string()));
+        assert_eq!(color("rgb(255, 0, 0)"), Ok("#ff0000".to_string()));
+        assert_eq!(color("rgba(0, 0, 255, 0.5)"), Ok("#0000ff80".to_string()));
+        assert_eq!(color("rgb(100% 0% 0% / 50%)"), Ok("#ff000080".to_string()));
+        assert_eq!(color("hsl(120, 100%, 25%)"), Ok("#008000".to_string()));
+        assert_eq!(
+            color("hsla(240deg 100% 50% / 0)"),
+            Ok("#0000ff00".to_string())
+        );
+        assert_eq!(color("rgb(256, 0, 0)"), Err(()));
+        assert_eq!(color("rgb(1, 2)"), Err(()));
+        assert_eq!(color("hsl(0, 120%, 50%)"), Err(()));
+        assert_eq!(color("notacolor"), Err(()));
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!("18pt".parse(), Ok(Length::new(18.0, Unit::Pt)));
+        assert_eq!(" 1.5 em ".parse(), Ok(Length::new(1.5, Unit::Em)));
+        assert_eq!("-2mm".parse(), Ok(Length::new(-2.0, Unit::Mm)));
+        assert_eq!("50%".parse(), Ok(Length::new(50.0, Unit::Percent)));
+        assert_eq!("0".parse(), Ok(Length::new(0.0, Unit::Pt)));
+        assert_eq!("12".parse::<Length>(), Err(()));
+        assert_eq!("12furlongs".parse::<Length>(), Err(()));
+        assert_eq!("pt".parse::<Length>(), Err(()));
+        assert_eq!(Length::new(1.5, Unit::Em).to_string(), "1.5em");
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!("50%".parse(), Ok(Percentage(50.0)));
+        assert_eq!(" 12.5 %".parse(), Ok(Percentage(12.5)));
+        assert_eq!(Percentage(25.0).fraction(), 0.25);
+        assert_eq!("50".parse::<Percentage>(), Err(()));
+        assert_eq!("inf%".parse::<Percentage>(), Err(()));
+    }
+
+    #[test]
+    fn conversions() {
+        let approx = |l: Option<Length>, value: f64, unit: Unit| {
+            let l = l.unwrap();
+            assert_eq!(l.unit, unit);
+            assert!((l.value - value).abs() < 1e-9, "{} != {}", l.value, value);
+        };
+        approx(Length::new(1.0, Unit::In).to_unit(Unit::Pt), 72.0, Unit::Pt);
+        approx(Length::new(96.0, Unit::Px).to_unit(Unit::In), 1.0, Unit::In);
+        approx(
+            Length::new(2.54, Unit::Cm).to_unit(Unit::Mm),
+            25.4,
+            Unit::Mm,
+        );
+        assert_eq!(Length::new(1.0, Unit::Em).to_pt(), None);
+        assert_eq!(
+            Length::new(2.0, Unit::Em).to_unit(Unit::Em),
+            Some(Length::new(2.0, Unit::Em))
+        );
+
+        let font_size = Length::new(10.0, Unit::Pt);
+        let root = Length::new(16.0, Unit::Px);
+        approx(
+            Length::new(1.5, Unit::Em).resolve(font_size, root),
+            15.0,
+            Unit::Pt,
+        );
+        approx(
+            Length::new(1.0, Unit::Ex).resolve(font_size, root),
+            5.0,
+            Unit::Pt,
+        );
+        approx(
+            Length::new(50.0, Unit::Percent).resolve(font_size, root),
+            5.0,
+            Unit::Pt,
+        );
+        approx(
+            Length::new(2.0, Unit::Rem).resolve(font_size, root),
+            32.0,
+            Unit::Px,
+        );
+        assert_eq!(
+            Length::new(1.0, Unit::Em).resolve(Length::new(1.0, Unit::Em), root),
+            None
+        );
+    }
+
+    #[test]
+    fn value_errors() {
+        assert_eq!(
+            parse_value::<Length>("sec", "margin", "2pt", "a length"),
+            Ok(Length::new(2.0, Unit::Pt))
+        );
+        let e = parse_value::<Length>("sec", "margin", "wide", "a length").unwrap_err();
+        assert_eq!(
+            e,
+            ValueError {
+                command: "sec".to_string(),
+                key: "margin".to_string(),
+                value: "wide".to_string(),
+                expected: "a length",
+                line: None,
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "invalid value `wide` for `margin` in command `sec`, expected a length"
+        );
+        assert_eq!(
+            e.at_line(Some(4)).to_string(),
+            "invalid value `wide` for `margin` in command `sec` on line 4, expected a length"
+        );
+    }
+}
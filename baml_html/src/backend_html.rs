@@ -1,5 +1,32 @@
-use baml_core::{Backend, Command, AST};
+use crate::dom::{DomNode, HtmlTag};
+use crate::mathml::{escape, math_element};
+use crate::serialize::{serialize, OutputMode};
+use baml_core::autolink::autolink;
+use baml_core::bibliography::{Bibliography, CitationStyle, Citations};
+use baml_core::callout::Callout;
+use baml_core::figures::{list_of_figures, number_figures, FigureEntry};
+use baml_core::footnotes::{number_footnotes, Footnote};
+use baml_core::glossary::Glossary;
+use baml_core::index::{Index, IndexTerm};
+use baml_core::links::resolve_links;
+use baml_core::math::{is_equation, parse_math};
+use baml_core::numbering::{number_sequentially, Numbering};
+use baml_core::outline::{Outline, OutlineEntry};
+use baml_core::quote::{build_quotes, Quote};
+use baml_core::refs::resolve_refs;
+use baml_core::shortcodes::Shortcodes;
+use baml_core::slug::assign_heading_ids;
+use baml_core::style::{Style, Weight};
+use baml_core::stylesheet::{Selector, StyleSheet};
+use baml_core::typography::Typography;
+use baml_core::values::{parse_value, Length};
+use baml_core::{
+    split_arguments, split_first_argument, trim_nodes, verbatim_source, ASTNode, Backend,
+    BasicCommandType, Command, AST,
+};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 mod ppm_extensions {
     use super::*;
@@ -45,75 +72,206 @@ mod ppm_extensions {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct HtmlTag {
-    tag_name: String,
-    attributes: Vec<(String, String)>,
-    child_nodes: Vec<DomNode>,
+/// Translates the portable style attributes into css
+pub fn css_declarations(style: &Style) -> Vec<(String, String)> {
+    let mut res = Vec::new();
+    if let Some(x) = style.font_size {
+        res.push(("font-size".to_string(), x.to_string()));
+    }
+    if let Some(x) = style.color {
+        res.push(("color".to_string(), x.to_string()));
+    }
+    if let Some(x) = style.align {
+        res.push(("text-align".to_string(), x.to_string()));
+    }
+    if let Some(x) = style.weight {
+        let x = match x {
+            Weight::Light => "300".to_string(),
+            x => x.to_string(),
+        };
+        res.push(("font-weight".to_string(), x));
+    }
+    if let Some(x) = style.margin {
+        res.push(("margin".to_string(), x.to_string()));
+    }
+    res
 }
 
-impl ToString for HtmlTag {
-    fn to_string(&self) -> String {
-        let x1: String = self
-            .attributes
-            .iter()
-            .map(|(k, v)| format!("{}={:?}", k, v))
-            .collect::<Vec<_>>()
-            .join(" ");
+/// Appends the declarations to the `style` attribute of the tag
+pub fn add_css(tag: &mut HtmlTag, decls: &[(String, String)]) {
+    if decls.is_empty() {
+        return;
+    }
+    let mut css = tag
+        .attr("style")
+        .map(|s| s.trim().trim_end_matches(';').to_string())
+        .filter(|s| !s.is_empty())
+        .into_iter()
+        .collect::<Vec<_>>();
+    css.extend(decls.iter().map(|(k, v)| format!("{}: {}", k, v)));
+    tag.set_attr("style", css.join("; "));
+}
 
-        let x2: String = self
-            .child_nodes
-            .iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-
-        format!(
-            "<{t}{}{}>{}</{t}>",
-            if x1.is_empty() { "" } else { " " },
-            x1,
-            x2,
-            t = self.tag_name
-        )
+/// The name of the tag that a generic command turns into
+pub fn html_tag_name(cmd: &str) -> String {
+    match cmd.parse() {
+        Ok(BasicCommandType::Section(n)) => format!("h{}", (n + 1).min(6)),
+        Ok(BasicCommandType::Strike) => "s".to_string(),
+        Ok(BasicCommandType::Underline) => "u".to_string(),
+        Ok(BasicCommandType::Subscript) => "sub".to_string(),
+        Ok(BasicCommandType::Superscript) => "sup".to_string(),
+        Ok(BasicCommandType::Mark) => "mark".to_string(),
+        Ok(BasicCommandType::Code) => "code".to_string(),
+        Ok(BasicCommandType::Kbd) => "kbd".to_string(),
+        Ok(BasicCommandType::Figure) => "figure".to_string(),
+        Ok(BasicCommandType::DefinitionList) => "dl".to_string(),
+        _ => cmd.to_string(),
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum DomNode {
-    Tag(HtmlTag),
-    Text(String),
+/// The css selector for the elements that a generic command turns into
+fn command_selector(cmd: &str) -> String {
+    use baml_core::BasicCommandType::*;
+    match cmd.parse() {
+        Ok(Link) => "a".to_string(),
+        Ok(Image) => "img".to_string(),
+        Ok(VertSpace) => "div".to_string(),
+        Ok(HorSpace) => "span".to_string(),
+        Ok(Toc) => "nav.toc".to_string(),
+        Ok(Footnote) => "sup.footnote-ref".to_string(),
+        Ok(ListOfFigures) => "nav.list-of-figures".to_string(),
+        Ok(Math) | Ok(DisplayMath) => "math".to_string(),
+        Ok(Equation) => "div.equation".to_string(),
+        Ok(Cite) => "span.citation".to_string(),
+        Ok(SmallCaps) => "span.smallcaps".to_string(),
+        Ok(References) => "section.references".to_string(),
+        Ok(Callout(_)) if cmd == "callout" => "aside.callout".to_string(),
+        Ok(Callout(kind)) => format!("aside.callout-{}", kind.to_string()),
+        Ok(Glossary) => "dl.glossary".to_string(),
+        Ok(Index) => "span.index-marker".to_string(),
+        Ok(PrintIndex) => "nav.index".to_string(),
+        Ok(Quote) => "blockquote".to_string(),
+        Ok(Task(false)) => "li.task:not(.task-done)".to_string(),
+        Ok(Task(true)) => "li.task-done".to_string(),
+        _ => html_tag_name(cmd),
+    }
 }
 
-impl ToString for DomNode {
-    fn to_string(&self) -> String {
-        match self {
-            DomNode::Tag(t) => t.to_string(),
-            DomNode::Text(s) => s.clone(),
+/// The css selector matching the elements that commands matching `sel` turn into
+fn css_selector(sel: &Selector) -> Option<String> {
+    let mut res = match (sel.backend.as_deref(), &sel.cmd) {
+        (None, Some(cmd)) => command_selector(cmd),
+        (None, None) => String::new(),
+        (Some("html"), Some(cmd)) => cmd.strip_prefix("tag.")?.to_string(),
+        _ => return None,
+    };
+    for class in &sel.classes {
+        res.push('.');
+        res.push_str(class);
+    }
+    if let Some(id) = &sel.id {
+        res.push('#');
+        res.push_str(id);
+    }
+    Some(res)
+}
+
+/// Moves the style attributes of the rules into a `<style>` element
+///
+/// The other attributes stay in the rules to be applied as usual
+fn style_element(sheet: &mut StyleSheet) -> Option<DomNode> {
+    let mut css = String::new();
+    for rule in &mut sheet.rules {
+        let selector = match css_selector(&rule.selector) {
+            Some(x) => x,
+            None => continue,
+        };
+        let mut tmp = Command {
+            backend: rule.selector.backend.clone(),
+            cmd: rule.selector.cmd.clone().unwrap_or_default(),
+            attributes: std::mem::take(&mut rule.attributes),
+            arguments: Vec::new(),
+            line: None,
+        };
+        let (style, warnings, errors) = Style::extract(&mut tmp);
+        for w in warnings {
+            eprintln!("warning: {}", w);
+        }
+        for e in errors {
+            eprintln!("error: {}", e);
         }
+        let decls = css_declarations(&style)
+            .into_iter()
+            .map(|(k, v)| format!("{}: {}", k, v))
+            .collect::<Vec<_>>();
+        if !decls.is_empty() {
+            css.push_str(&format!("{} {{ {} }}\n", selector, decls.join("; ")));
+        }
+        rule.attributes = tmp.attributes;
+    }
+    if css.is_empty() {
+        None
+    } else {
+        Some(DomNode::Tag(HtmlTag::new("style").with_text(css)))
+    }
+}
+
+/// Creates a tag with the attributes of the command, translating style attributes into css
+///
+/// The attributes are taken out of `cmd`, its arguments are left alone
+pub fn tag_from_attributes(tag_name: &str, cmd: &mut Command) -> HtmlTag {
+    let (style, warnings, errors) = Style::extract(cmd);
+    for w in warnings {
+        eprintln!("warning: {}", w);
+    }
+    for e in errors {
+        eprintln!("error: {}", e);
     }
+    let mut tag = HtmlTag::new(tag_name).with_attributes(std::mem::take(&mut cmd.attributes));
+    add_css(&mut tag, &css_declarations(&style));
+    tag
 }
 
-// impl DomNode {
-//     pub fn child_nodes(&self) -> &[Self] {
-//         match self {
-//             DomNode::Text(_) => &[],
-//             DomNode::Tag(t) => &t.child_nodes,
-//         }
-//     }
-//
-//     // pub fn child_nodes_mut(&mut self) -> Option<&mut Vec<Self>> {
-//     //     match self {
-//     //         DomNode::Text(_) => &mut vec![],
-//     //         DomNode::Tag(t) => &mut t.child_nodes,
-//     //     }
-//     // }
-// }
+/// `<span class="number">` holding a section number
+fn number_span(number: String) -> HtmlTag {
+    HtmlTag::new("span").with_class("number").with_text(number)
+}
+
+type DomTransform = Rc<dyn Fn(&mut DomNode)>;
 
 #[derive(Clone)]
 pub struct BackendHtml {
     template: String,
     vars: HashMap<String, String>,
     special_vars: HashMap<String, String>,
+    output_mode: OutputMode,
+    dom_transforms: Vec<DomTransform>,
+    stylesheet: StyleSheet,
+    style_block: bool,
+    autolink: bool,
+    smart_typography: bool,
+    heading_anchors: bool,
+    outline: Outline,
+    numbering: Option<Numbering>,
+    /// Footnotes that were referenced but not yet written out, with their rendered content
+    pending_footnotes: Vec<(usize, Vec<DomNode>)>,
+    footnotes_per_section: bool,
+    figures: Vec<FigureEntry>,
+    /// The bibliography of the current document
+    bibliography: Bibliography,
+    /// The bibliography of documents without `!bibliography`
+    default_bibliography: Option<Bibliography>,
+    /// The directory `!bibliography` paths are relative to
+    document_dir: PathBuf,
+    citations: Citations,
+    references_written: bool,
+    glossary: Glossary,
+    /// An index covering several documents, otherwise each document has its own
+    shared_index: Option<Index>,
+    index: Index,
+    shortcodes: Shortcodes,
+    /// The source line of the command being rendered, for error messages
+    current_line: Option<usize>,
 }
 
 impl BackendHtml {
@@ -134,20 +292,588 @@ impl BackendHtml {
             template,
             vars,
             special_vars: HashMap::new(),
+            output_mode: OutputMode::default(),
+            dom_transforms: Vec::new(),
+            stylesheet: StyleSheet::default(),
+            style_block: false,
+            autolink: false,
+            smart_typography: false,
+            heading_anchors: false,
+            outline: Outline::default(),
+            numbering: None,
+            pending_footnotes: Vec::new(),
+            footnotes_per_section: false,
+            figures: Vec::new(),
+            bibliography: Bibliography::default(),
+            default_bibliography: None,
+            document_dir: PathBuf::new(),
+            citations: Citations::default(),
+            references_written: false,
+            glossary: Glossary::default(),
+            shared_index: None,
+            index: Index::default(),
+            shortcodes: Shortcodes::default(),
+            current_line: None,
         }
     }
 
-    pub fn node_from_command(&mut self, cmd: Command) -> DomNode {
+    /// Sets the bibliography for citations in documents without `!bibliography`
+    pub fn set_bibliography(&mut self, bibliography: Option<Bibliography>) {
+        self.default_bibliography = bibliography;
+    }
+
+    /// Sets the directory of the next document, which paths in it (like `!bibliography`) are relative to
+    pub fn set_document_dir(&mut self, dir: PathBuf) {
+        self.document_dir = dir;
+    }
+
+    /// Sets the index that `[printindex]` writes, for an index of several documents
+    ///
+    /// The locations have to match the ids `Index::collect` assigns to the markers of each document.
+    pub fn set_index(&mut self, index: Option<Index>) {
+        self.shared_index = index;
+    }
+
+    /// Numbers the headings of documents by default
+    ///
+    /// Documents can override this with `!numbering`
+    pub fn set_numbering(&mut self, numbering: Option<Numbering>) {
+        self.numbering = numbering;
+    }
+
+    /// Whether headings get a link to themselves, for copying links to sections
+    pub fn set_heading_anchors(&mut self, heading_anchors: bool) {
+        self.heading_anchors = heading_anchors;
+    }
+
+    /// Whether quotes, dashes and ellipses are typeset in the style of the document's `!lang`
+    ///
+    /// Documents can override this with `!typography yes/no`
+    pub fn set_smart_typography(&mut self, smart_typography: bool) {
+        self.smart_typography = smart_typography;
+    }
+
+    /// Whether bare urls and email addresses are turned into links
+    ///
+    /// Documents can override this with `!autolink yes/no`
+    pub fn set_autolink(&mut self, autolink: bool) {
+        self.autolink = autolink;
+    }
+
+    /// Sets the project-level extra symbols, which documents can extend with `!symbols`
+    pub fn set_shortcodes(&mut self, shortcodes: Shortcodes) {
+        self.shortcodes = shortcodes;
+    }
+
+    /// Sets the project-level style rules, which come before the ones of each document
+    pub fn set_stylesheet(&mut self, sheet: StyleSheet) {
+        self.stylesheet = sheet;
+    }
+
+    /// Whether the style attributes of style rules are emitted as a `<style>` element instead of inline
+    pub fn set_style_block(&mut self, style_block: bool) {
+        self.style_block = style_block;
+    }
+
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+    }
+
+    /// Registers a function that may modify the finished DOM before it is serialized into `%content`
+    ///
+    /// The root node is a `DomNode::Fragment` holding the top-level nodes.
+    /// Transforms are run in the order in which they were added.
+    pub fn add_dom_transform<F: Fn(&mut DomNode) + 'static>(&mut self, f: F) {
+        self.dom_transforms.push(Rc::new(f));
+    }
+
+    pub fn render_nodes(&mut self, nodes: Vec<ASTNode>) -> Vec<DomNode> {
+        nodes
+            .into_iter()
+            .filter_map(|node| self.handle_node(node))
+            .collect()
+    }
+
+    pub fn node_from_command(&mut self, mut cmd: Command) -> DomNode {
         // todo: improve this
-        DomNode::Tag(HtmlTag {
-            tag_name: cmd.cmd,
-            attributes: cmd.attributes,
-            child_nodes: cmd
-                .arguments
-                .into_iter()
-                .filter_map(|arg| self.handle_node(arg))
-                .collect(),
-        })
+        let tag_name = match cmd.backend {
+            // `html@tag.<name>` is always the tag of that name
+            Some(_) => cmd.cmd.clone(),
+            None => html_tag_name(&cmd.cmd),
+        };
+        let tag = tag_from_attributes(&tag_name, &mut cmd);
+        if cmd.is_verbatim() {
+            // like `[code a < b; c]`, which is shown as written
+            return DomNode::Tag(tag.with_text(escape(&verbatim_source(&cmd.arguments))));
+        }
+        let children = self.render_nodes(cmd.arguments);
+        DomNode::Tag(tag.with_children(children))
+    }
+
+    /// Renders `Section(n)` as `<h{n + 1}>`, with an optional self-link
+    fn heading_node(&mut self, mut cmd: Command) -> DomNode {
+        cmd.attributes
+            .retain(|(k, _)| k != "toc" && k != "numbered");
+        let number = cmd
+            .attributes
+            .iter()
+            .position(|(k, _)| k == "number")
+            .map(|i| cmd.attributes.remove(i).1);
+        let mut tag = tag_from_attributes(&html_tag_name(&cmd.cmd), &mut cmd);
+        if let Some(number) = number {
+            tag.append_child(number_span(number));
+            tag.append_child(" ");
+        }
+        let children = self.render_nodes(cmd.arguments);
+        tag.child_nodes_mut().extend(children);
+        if self.heading_anchors {
+            if let Some(id) = tag.id().map(|id| format!("#{}", id)) {
+                tag.append_child(
+                    HtmlTag::new("a")
+                        .with_class("anchor")
+                        .with_attr("href", id)
+                        .with_attr("aria-hidden", "true")
+                        .with_text("#"),
+                );
+            }
+        }
+        DomNode::Tag(tag)
+    }
+
+    /// Renders `[footnote ...]` as a link to the note, which is written out later
+    fn footnote_node(&mut self, cmd: Command) -> Option<DomNode> {
+        let note = Footnote::from_command(&cmd)?;
+        // reserve the place first, so that notes in this note come after it
+        let i = self.pending_footnotes.len();
+        self.pending_footnotes.push((note.number, Vec::new()));
+        self.pending_footnotes[i].1 = self.render_nodes(note.content);
+        let link = HtmlTag::new("a")
+            .with_attr("href", format!("#fn-{}", note.number))
+            .with_text(note.number.to_string());
+        Some(DomNode::Tag(
+            HtmlTag::new("sup")
+                .with_class("footnote-ref")
+                .with_id(format!("fnref-{}", note.number))
+                .with_child(link),
+        ))
+    }
+
+    /// Writes out the pending footnotes as `<section class="footnotes">`, with links back to the references
+    fn flush_footnotes(&mut self) -> Option<DomNode> {
+        if self.pending_footnotes.is_empty() {
+            return None;
+        }
+        let mut ol = HtmlTag::new("ol");
+        for (number, content) in std::mem::take(&mut self.pending_footnotes) {
+            let back = HtmlTag::new("a")
+                .with_class("footnote-back")
+                .with_attr("href", format!("#fnref-{}", number))
+                .with_attr("aria-label", "back to the text")
+                .with_text("&#8617;");
+            ol.append_child(
+                HtmlTag::new("li")
+                    .with_id(format!("fn-{}", number))
+                    .with_attr("value", number.to_string())
+                    .with_children(content)
+                    .with_text(" ")
+                    .with_child(back),
+            );
+        }
+        Some(DomNode::Tag(
+            HtmlTag::new("section")
+                .with_class("footnotes")
+                .with_child(ol),
+        ))
+    }
+
+    /// Renders `[img <source>; <alt text>]`
+    fn image_node(&mut self, mut cmd: Command) -> DomNode {
+        let mut tag = tag_from_attributes("img", &mut cmd);
+        let (src, alt) = split_first_argument(cmd.arguments);
+        let src = src.iter().map(ASTNode::plain_text).collect::<String>();
+        let alt = alt.iter().map(ASTNode::plain_text).collect::<String>();
+        tag.set_attr("src", src.trim());
+        tag.set_attr("alt", alt.trim());
+        DomNode::Tag(tag)
+    }
+
+    /// Renders `[math]`, `[displaymath]` and `[equation]` as MathML
+    ///
+    /// Equations are wrapped in `<div class="equation">` together with their number
+    fn math_node(&mut self, mut cmd: Command) -> DomNode {
+        let kind = cmd.cmd.parse();
+        let equation = kind == Ok(BasicCommandType::Equation);
+        let number = cmd
+            .attributes
+            .iter()
+            .position(|(k, _)| k == "number")
+            .map(|i| cmd.attributes.remove(i).1);
+        cmd.attributes.retain(|(k, _)| k != "numbered");
+        let outer = tag_from_attributes(if equation { "div" } else { "math" }, &mut cmd);
+        let source = verbatim_source(&cmd.arguments);
+        let math = match parse_math(source.trim()) {
+            Ok(nodes) => math_element(&nodes, kind != Ok(BasicCommandType::Math)),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                let escaped = escape(&source);
+                HtmlTag::new("code")
+                    .with_class("math-error")
+                    .with_text(escaped)
+            }
+        };
+        if !equation {
+            return DomNode::Tag(math.with_attributes(outer.attributes().to_vec()));
+        }
+        let mut tag = outer.with_child(math);
+        tag.add_class("equation");
+        if let Some(number) = number {
+            tag.append_child(number_span(format!("({})", number)));
+        }
+        DomNode::Tag(tag)
+    }
+
+    /// Renders `[fig <content>; <caption>]` as `<figure>` with a `<figcaption>`
+    fn figure_node(&mut self, mut cmd: Command) -> DomNode {
+        cmd.attributes.retain(|(k, _)| k != "numbered");
+        let number = cmd
+            .attributes
+            .iter()
+            .position(|(k, _)| k == "number")
+            .map(|i| cmd.attributes.remove(i).1);
+        let tag = tag_from_attributes("figure", &mut cmd);
+        let (content, caption) = split_first_argument(cmd.arguments);
+        let mut tag = tag.with_children(self.render_nodes(content));
+        if number.is_some() || !caption.is_empty() {
+            let mut figcaption = HtmlTag::new("figcaption");
+            if let Some(number) = number {
+                figcaption.append_child(
+                    HtmlTag::new("span")
+                        .with_class("number")
+                        .with_text(format!("Figure {}:", number)),
+                );
+                figcaption.append_child(" ");
+            }
+            figcaption
+                .child_nodes_mut()
+                .extend(self.render_nodes(caption));
+            tag.append_child(figcaption);
+        }
+        DomNode::Tag(tag)
+    }
+
+    /// Renders `[listoffigures]` as a list of links to the numbered figures
+    fn list_of_figures_node(&mut self, mut cmd: Command) -> Option<DomNode> {
+        if self.figures.is_empty() {
+            return None;
+        }
+        let mut tag = tag_from_attributes("nav", &mut cmd);
+        tag.add_class("list-of-figures");
+        let mut ul = HtmlTag::new("ul");
+        for entry in self.figures.clone() {
+            let mut a = HtmlTag::new("a").with_child(number_span(entry.number));
+            a.append_child(" ");
+            a.child_nodes_mut().extend(self.render_nodes(entry.caption));
+            if let Some(id) = entry.id {
+                a.set_attr("href", format!("#{}", id));
+            }
+            ul.append_child(HtmlTag::new("li").with_child(a));
+        }
+        Some(DomNode::Tag(tag.with_child(ul)))
+    }
+
+    /// Renders callouts as `<aside class="callout callout-<kind>">` starting with the title
+    fn callout_node(&mut self, mut cmd: Command) -> DomNode {
+        let callout = match Callout::extract(&mut cmd) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                cmd.attributes.retain(|(k, _)| k != "kind" && k != "title");
+                Callout {
+                    kind: Default::default(),
+                    title: None,
+                }
+            }
+        };
+        let mut tag = tag_from_attributes("aside", &mut cmd);
+        tag.add_class("callout");
+        tag.add_class(&format!("callout-{}", callout.kind.to_string()));
+        tag.set_attr("role", "note");
+        let title = HtmlTag::new("p")
+            .with_class("callout-title")
+            .with_text(callout.title());
+        let children = self.render_nodes(cmd.arguments);
+        DomNode::Tag(tag.with_child(title).with_children(children))
+    }
+
+    /// Renders `[deflist <term>; <definition>; ...]` as `<dl>`
+    ///
+    /// Without separate arguments, the children are kept as they are (for `[deflist [dt ...] [dd ...]]`)
+    fn definition_list_node(&mut self, mut cmd: Command) -> DomNode {
+        if !cmd
+            .arguments
+            .iter()
+            .any(|n| matches!(n, ASTNode::ArgSeparator(_)))
+        {
+            return self.node_from_command(cmd);
+        }
+        let mut tag = tag_from_attributes("dl", &mut cmd);
+        let args = split_arguments(std::mem::take(&mut cmd.arguments));
+        if args.len() % 2 == 1 {
+            eprintln!("warning: the last term of a `[deflist]` has no definition");
+        }
+        for (i, arg) in args.into_iter().enumerate() {
+            let tag_name = if i % 2 == 0 { "dt" } else { "dd" };
+            let children = self.render_nodes(trim_nodes(arg));
+            tag.append_child(HtmlTag::new(tag_name).with_children(children));
+        }
+        DomNode::Tag(tag)
+    }
+
+    /// Renders `[glossary]` as a `<dl class="glossary">` of the defined terms, sorted by term
+    fn glossary_node(&mut self, mut cmd: Command) -> Option<DomNode> {
+        if self.glossary.is_empty() {
+            return None;
+        }
+        let mut tag = tag_from_attributes("dl", &mut cmd);
+        tag.add_class("glossary");
+        for entry in self.glossary.clone().sorted() {
+            let dt = HtmlTag::new("dt")
+                .with_id(entry.id.clone())
+                .with_text(entry.term.clone());
+            let dd = HtmlTag::new("dd").with_children(self.render_nodes(entry.definition.clone()));
+            tag.append_child(dt);
+            tag.append_child(dd);
+        }
+        Some(DomNode::Tag(tag))
+    }
+
+    /// Renders the terms of an index as a list, with numbered links to their locations
+    fn index_list(&mut self, terms: &[&IndexTerm]) -> HtmlTag {
+        let mut ul = HtmlTag::new("ul");
+        for term in terms {
+            let mut li = HtmlTag::new("li").with_text(term.term.clone());
+            for (i, location) in term.locations.iter().enumerate() {
+                li.append_child(if i == 0 { " " } else { ", " });
+                li.append_child(
+                    HtmlTag::new("a")
+                        .with_attr("href", location.as_str())
+                        .with_text((i + 1).to_string()),
+                );
+            }
+            if !term.subterms.is_empty() {
+                li.append_child(self.index_list(&term.subterms.iter().collect::<Vec<_>>()));
+            }
+            ul.append_child(li);
+        }
+        ul
+    }
+
+    /// Renders `[printindex]` as `<nav class="index">`, with the terms grouped by their first letter
+    fn print_index_node(&mut self, mut cmd: Command) -> Option<DomNode> {
+        let index = self.shared_index.clone().unwrap_or(self.index.clone());
+        if index.is_empty() {
+            return None;
+        }
+        let mut tag = tag_from_attributes("nav", &mut cmd);
+        tag.add_class("index");
+        for (letter, terms) in index.groups() {
+            let group = HtmlTag::new("div")
+                .with_class("index-group")
+                .with_child(
+                    HtmlTag::new("p")
+                        .with_class("index-letter")
+                        .with_text(letter),
+                )
+                .with_child(self.index_list(&terms));
+            tag.append_child(group);
+        }
+        Some(DomNode::Tag(tag))
+    }
+
+    /// Renders quotes as `<blockquote>`, ending with `<footer>— author, <cite>source</cite></footer>`
+    fn quote_node(&mut self, quote: Quote) -> DomNode {
+        let has_attribution = quote.has_attribution();
+        let mut cmd = Command {
+            backend: None,
+            cmd: BasicCommandType::Quote.to_string(),
+            attributes: quote.attributes,
+            arguments: Vec::new(),
+            line: quote.line,
+        };
+        let mut tag = tag_from_attributes("blockquote", &mut cmd);
+        let children = self.render_nodes(quote.content);
+        tag.child_nodes_mut().extend(children);
+        if has_attribution {
+            let mut footer = HtmlTag::new("footer").with_text("— ");
+            if let Some(author) = quote.author {
+                footer.append_child(author);
+                if quote.source.is_some() {
+                    footer.append_child(", ");
+                }
+            }
+            if let Some(source) = quote.source {
+                footer.append_child(HtmlTag::new("cite").with_text(source));
+            }
+            tag.append_child(footer);
+        }
+        DomNode::Tag(tag)
+    }
+
+    /// Renders task items as `<li class="task">` starting with a disabled checkbox
+    fn task_node(&mut self, done: bool, mut cmd: Command) -> DomNode {
+        let mut tag = tag_from_attributes("li", &mut cmd);
+        tag.add_class("task");
+        let mut checkbox = HtmlTag::new("input")
+            .with_attr("type", "checkbox")
+            .with_attr("disabled", "");
+        if done {
+            tag.add_class("task-done");
+            checkbox.set_attr("checked", "");
+        }
+        tag.append_child(checkbox);
+        tag.append_child(" ");
+        let children = self.render_nodes(trim_nodes(cmd.arguments));
+        DomNode::Tag(tag.with_children(children))
+    }
+
+    /// Renders `[references]` as the list of cited entries
+    ///
+    /// Without that command, the list is added at the end of the document
+    fn references_node(&mut self, mut cmd: Command) -> Option<DomNode> {
+        self.references_written = true;
+        let references = self.citations.reference_list(&self.bibliography);
+        if references.is_empty() {
+            return None;
+        }
+        let mut tag = tag_from_attributes("section", &mut cmd);
+        tag.add_class("references");
+        let mut list = HtmlTag::new(match self.citations.style {
+            CitationStyle::Numeric => "ol",
+            CitationStyle::AuthorYear => "ul",
+        });
+        for reference in references {
+            let mut li = HtmlTag::new("li").with_id(reference.id);
+            if let Some(number) = reference.number {
+                li.set_attr("value", number);
+            }
+            li.child_nodes_mut()
+                .extend(self.render_nodes(reference.content));
+            list.append_child(li);
+        }
+        Some(DomNode::Tag(tag.with_child(list)))
+    }
+
+    /// Renders outline entries as nested lists of links to the headings
+    fn outline_list(&mut self, entries: &[OutlineEntry]) -> HtmlTag {
+        let mut ul = HtmlTag::new("ul");
+        for entry in entries {
+            let mut a = HtmlTag::new("a");
+            if let Some(number) = &entry.number {
+                a.append_child(number_span(number.clone()));
+                a.append_child(" ");
+            }
+            a.child_nodes_mut()
+                .extend(self.render_nodes(entry.title.clone()));
+            if let Some(id) = &entry.id {
+                a.set_attr("href", format!("#{}", id));
+            }
+            let mut li = HtmlTag::new("li").with_child(a);
+            if !entry.children.is_empty() {
+                li.append_child(self.outline_list(&entry.children));
+            }
+            ul.append_child(li);
+        }
+        ul
+    }
+
+    /// Renders the outline as `<nav class="toc">`, `None` if there are no headings
+    fn toc_tag(&mut self, mut tag: HtmlTag, outline: &Outline) -> Option<HtmlTag> {
+        if outline.is_empty() {
+            return None;
+        }
+        tag.add_class("toc");
+        Some(tag.with_child(self.outline_list(&outline.entries)))
+    }
+
+    /// Renders `[toc]`, whose `depth` attribute limits how many levels are shown
+    fn toc_node(&mut self, mut cmd: Command) -> Option<DomNode> {
+        let mut outline = self.outline.clone();
+        if let Some(i) = cmd.attributes.iter().position(|(k, _)| k == "depth") {
+            let (k, v) = cmd.attributes.remove(i);
+            match parse_value::<usize>(&cmd.cmd, &k, &v, "a number") {
+                Ok(depth) => outline.truncate(depth),
+                Err(e) => eprintln!("error: {}", e.at_line(cmd.line)),
+            }
+        }
+        let tag = tag_from_attributes("nav", &mut cmd);
+        self.toc_tag(tag, &outline).map(DomNode::Tag)
+    }
+
+    /// The table of contents for the `%toc` template variable, limited by `!toc-depth`
+    fn toc_var(&mut self, meta: &HashMap<String, String>) -> String {
+        let mut outline = self.outline.clone();
+        if let Some(v) = meta.get("toc-depth") {
+            match parse_value::<usize>("!toc-depth", "value", v.trim(), "a number") {
+                Ok(depth) => outline.truncate(depth),
+                Err(e) => eprintln!("error: {}", e),
+            }
+        }
+        match self.toc_tag(HtmlTag::new("nav"), &outline) {
+            Some(tag) => serialize(&[DomNode::Tag(tag)], self.output_mode),
+            None => String::new(),
+        }
+    }
+
+    /// Renders `[link <target>; <text>]`, where the text defaults to the target
+    fn link_node(&mut self, mut cmd: Command) -> DomNode {
+        let mut tag = tag_from_attributes("a", &mut cmd);
+        let (target, text) = split_first_argument(cmd.arguments);
+        let href = target
+            .iter()
+            .map(ASTNode::plain_text)
+            .collect::<String>()
+            .trim()
+            .to_string();
+        let text = if text.is_empty() {
+            vec![DomNode::Text(href.clone())]
+        } else {
+            self.render_nodes(text)
+        };
+        // unresolved references were already reported, so they just don't link anywhere
+        if !href.starts_with('@') {
+            tag.set_attr("href", href);
+        }
+        DomNode::Tag(tag.with_children(text))
+    }
+
+    /// Renders `vspace` and `hspace`, whose argument is the length of the space
+    fn space_node(&mut self, cmd: Command) -> Option<DomNode> {
+        let length = match parse_value::<Length>(
+            &cmd.cmd,
+            "argument 1",
+            cmd.plain_text().trim(),
+            "a length",
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("error: {}", e.at_line(cmd.line));
+                return None;
+            }
+        };
+        let (tag_name, css) = if cmd.cmd == "vspace" {
+            ("div", vec![("height".to_string(), length.to_string())])
+        } else {
+            (
+                "span",
+                vec![
+                    ("display".to_string(), "inline-block".to_string()),
+                    ("width".to_string(), length.to_string()),
+                ],
+            )
+        };
+        let mut tag = HtmlTag::new(tag_name).with_attributes(cmd.attributes);
+        add_css(&mut tag, &css);
+        Some(DomNode::Tag(tag))
     }
 
     pub fn set_special_vars(&mut self, content_var: String, meta: &HashMap<String, String>) {
@@ -184,8 +910,16 @@ impl Backend for BackendHtml {
         DomNode::Text(text.replace("\n", "<br />\n"))
     }
 
+    fn emit_quote(&mut self, quote: Quote) -> Option<DomNode> {
+        let parent_line = std::mem::replace(&mut self.current_line, quote.line);
+        let res = self.quote_node(quote);
+        self.current_line = parent_line;
+        Some(res)
+    }
+
     fn run_command(&mut self, mut cmd: Command) -> Option<DomNode> {
-        match cmd.backend.as_deref() {
+        let parent_line = std::mem::replace(&mut self.current_line, cmd.line);
+        let res = match cmd.backend.as_deref() {
             Some("html") => {
                 // this serves the purpose of allowing you to insert any html tag with nice syntax
                 // you could probably insert a tag as raw html but that's ugly
@@ -197,22 +931,290 @@ impl Backend for BackendHtml {
                 }
             }
             Some(_) => None,
-            None => {
+            None => match cmd.cmd.parse::<BasicCommandType>() {
+                Ok(t) => self.run_basic_command(t, cmd.attributes, cmd.arguments),
                 // todo: handle some of these commands differently
-                Some(self.node_from_command(cmd))
+                Err(()) => Some(self.node_from_command(cmd)),
+            },
+        };
+        self.current_line = parent_line;
+        res
+    }
+
+    fn run_basic_command(
+        &mut self,
+        cmd: BasicCommandType,
+        attrs: Vec<(String, String)>,
+        args: Vec<ASTNode>,
+    ) -> Option<DomNode> {
+        use baml_core::BasicCommandType::*;
+        let command = Command {
+            backend: None,
+            cmd: cmd.to_string(),
+            attributes: attrs,
+            arguments: args,
+            line: self.current_line,
+        };
+        match cmd {
+            VertSpace | HorSpace => self.space_node(command),
+            Link => Some(self.link_node(command)),
+            // with `!footnotes section`, the notes of a top-level section come before the next one
+            Section(0) if self.footnotes_per_section => {
+                let notes = self.flush_footnotes();
+                let heading = self.heading_node(command);
+                Some(match notes {
+                    Some(notes) => DomNode::Fragment(vec![notes, heading]),
+                    None => heading,
+                })
+            }
+            Section(_) => Some(self.heading_node(command)),
+            Footnote => self.footnote_node(command),
+            Figure => Some(self.figure_node(command)),
+            Image => Some(self.image_node(command)),
+            Math | DisplayMath | Equation => Some(self.math_node(command)),
+            Cite | SmallCaps => {
+                let mut command = command;
+                let mut tag = tag_from_attributes("span", &mut command);
+                if cmd == Cite {
+                    tag.add_class("citation");
+                } else {
+                    tag.add_class("smallcaps");
+                    add_css(
+                        &mut tag,
+                        &[("font-variant".to_string(), "small-caps".to_string())],
+                    );
+                }
+                let children = self.render_nodes(command.arguments);
+                Some(DomNode::Tag(tag.with_children(children)))
+            }
+            References => self.references_node(command),
+            Callout(_) => Some(self.callout_node(command)),
+            DefinitionList => Some(self.definition_list_node(command)),
+            Glossary => self.glossary_node(command),
+            // the marker is only a target for the links from the index
+            Index => {
+                let mut command = command;
+                let mut tag = tag_from_attributes("span", &mut command);
+                tag.add_class("index-marker");
+                Some(DomNode::Tag(tag))
             }
+            PrintIndex => self.print_index_node(command),
+            Quote => Some(self.quote_node(baml_core::quote::Quote::from_command(command))),
+            Task(done) => Some(self.task_node(done, command)),
+            ListOfFigures => self.list_of_figures_node(command),
+            Toc => self.toc_node(command),
+            _ => Some(self.node_from_command(command)),
         }
     }
 
-    fn compile_ast(&mut self, ast: AST) -> String {
-        let content = ast
-            .nodes
-            .into_iter()
-            .filter_map(|node| self.handle_node(node))
-            .map(|n| n.to_string())
-            .collect::<Vec<_>>()
-            .join("");
+    fn compile_ast(&mut self, mut ast: AST) -> String {
+        if ast.metadata_flag("autolink").unwrap_or(self.autolink) {
+            autolink(&mut ast.nodes);
+        }
+        // after autolinking, so that urls (like `http://a.com/:tada:`) are left alone
+        let mut shortcodes = self.shortcodes.clone();
+        match Shortcodes::from_metadata(&ast.metadata) {
+            Ok(s) => shortcodes.extend(s),
+            Err(e) => eprintln!("error: {}", e),
+        }
+        for w in shortcodes.expand(&mut ast.nodes) {
+            eprintln!("warning: {}", w);
+        }
+        if ast
+            .metadata_flag("typography")
+            .unwrap_or(self.smart_typography)
+        {
+            let lang = ast.metadata.get("lang").map(String::as_str).unwrap_or("en");
+            Typography::for_lang(lang).apply(&mut ast.nodes);
+        }
+        for w in resolve_links(&mut ast) {
+            eprintln!("warning: {}", w);
+        }
+        let (glossary, warnings) = Glossary::collect(&mut ast.nodes);
+        for w in warnings {
+            eprintln!("warning: {}", w);
+        }
+        glossary.mark_terms(&mut ast.nodes);
+        self.glossary = glossary;
+        self.index = Index::collect(&mut ast.nodes, "");
+        let mut sheet = self.stylesheet.clone();
+        match StyleSheet::from_metadata(&ast.metadata) {
+            Ok(s) => sheet.extend(s),
+            Err(e) => eprintln!("error: {}", e),
+        }
+        let style_element = if self.style_block {
+            style_element(&mut sheet)
+        } else {
+            None
+        };
+        sheet.apply(&mut ast.nodes);
+        number_figures(&mut ast.nodes);
+        number_sequentially(&mut ast.nodes, is_equation, "eq");
+        assign_heading_ids(&mut ast.nodes);
+        let numbering = match Numbering::from_metadata(&ast.metadata) {
+            Ok(Some(n)) => Some(n),
+            Ok(None) if ast.metadata.contains_key("numbering") => None,
+            Ok(None) => self.numbering.clone(),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                self.numbering.clone()
+            }
+        };
+        if let Some(numbering) = numbering {
+            numbering.apply(&mut ast.nodes);
+        }
+        for e in resolve_refs(&mut ast.nodes) {
+            eprintln!("error: {}", e);
+        }
+        self.outline = Outline::from_nodes(&ast.nodes);
+        self.figures = list_of_figures(&ast.nodes);
+        let style = match ast.metadata.get("citation-style") {
+            Some(v) => parse_value(
+                "!citation-style",
+                "value",
+                v.trim(),
+                "`numeric` or `author-year`",
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                CitationStyle::default()
+            }),
+            None => CitationStyle::default(),
+        };
+        let bibliography = Bibliography::from_metadata(&ast.metadata, &self.document_dir)
+            .unwrap_or_else(|e| {
+                eprintln!("error: invalid bibliography: {}", e);
+                None
+            });
+        self.bibliography = bibliography
+            .or_else(|| self.default_bibliography.clone())
+            .unwrap_or_default();
+        let (citations, warnings) = Citations::resolve(&mut ast.nodes, &self.bibliography, style);
+        for w in warnings {
+            eprintln!("warning: {}", w);
+        }
+        self.citations = citations;
+        number_footnotes(&mut ast.nodes);
+        self.footnotes_per_section = match ast.metadata.get("footnotes").map(|s| s.trim()) {
+            None | Some("end") => false,
+            Some("section") => true,
+            Some(v) => {
+                eprintln!(
+                    "error: invalid value `{}` for `!footnotes`, expected `end` or `section`",
+                    v
+                );
+                false
+            }
+        };
+
+        build_quotes(&mut ast.nodes);
+
+        // before the content, so that the notes of the document are all its own
+        let toc = self.toc_var(&ast.metadata);
+        self.pending_footnotes.clear();
+        self.references_written = false;
+        let mut nodes = self.render_nodes(ast.nodes);
+        if !self.references_written {
+            nodes.extend(self.references_node(Command {
+                backend: None,
+                cmd: BasicCommandType::References.to_string(),
+                attributes: Vec::new(),
+                arguments: Vec::new(),
+                line: None,
+            }));
+        }
+        nodes.extend(self.flush_footnotes());
+        if let Some(el) = style_element {
+            nodes.insert(0, el);
+        }
+        let mut root = DomNode::Fragment(nodes);
+        for transform in &self.dom_transforms {
+            transform(&mut root);
+        }
+        let content = serialize(std::slice::from_ref(&root), self.output_mode);
         self.set_special_vars(content, &ast.metadata);
+        self.special_vars.insert("toc".to_string(), toc);
         self.main()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::BackendHtml;
+    use baml_core::index::Index;
+    use baml_core::{parse, Backend};
+    use std::collections::HashMap;
+
+    fn render(source: &str) -> String {
+        let mut backend = BackendHtml::new("%content%".to_string(), HashMap::new());
+        backend.compile_ast(parse(source.to_string()).unwrap())
+    }
+
+    #[test]
+    fn inline_whitespace() {
+        assert_eq!(render("[b foo][i bar]"), "<b>foo</b><i>bar</i>");
+        assert_eq!(render("foo[b bar]baz"), "foo<b>bar</b>baz");
+        assert_eq!(render("un[i believ]able"), "un<i>believ</i>able");
+        assert_eq!(render("a [b x] b"), "a <b>x</b> b");
+        assert_eq!(render("[b  x]"), "<b>x</b>");
+        assert_eq!(render("[b \\  x]"), "<b> x</b>");
+    }
+
+    #[test]
+    fn separator_whitespace() {
+        assert_eq!(render("[b foo; bar]"), "<b>foo bar</b>");
+        assert_eq!(render("[b foo;bar]"), "<b>foobar</b>");
+        assert_eq!(render("[b foo;\tbar]"), "<b>foo\tbar</b>");
+        assert_eq!(render("[b foo [i x]; bar]"), "<b>foo <i>x</i> bar</b>");
+    }
+
+    #[test]
+    fn html_element_names() {
+        assert_eq!(
+            render("[s{color = red} a][strike{color = red} a][u a][underline a][sub 1][subscript 1]"),
+            "<s color=\"red\">a</s><s style=\"color: #ff0000\">a</s><u>a</u><u>a</u><sub>1</sub><sub>1</sub>"
+        );
+        assert_eq!(
+            render("[mark a][highlight a][code a<b][verb a<b][kbd C][key C]"),
+            "<mark>a</mark><mark>a</mark><code>a&lt;b</code><code>a&lt;b</code><kbd>C</kbd><kbd>C</kbd>"
+        );
+        assert_eq!(render("[dl a; b]"), "<dl>a b</dl>");
+        assert_eq!(render("[deflist a; b]"), "<dl><dt>a</dt><dd>b</dd></dl>");
+        assert_eq!(render("[figure a]"), "<figure>a</figure>");
+        assert_eq!(
+            render("[fig a; b]"),
+            "<figure id=\"figure-1\">a<figcaption><span class=\"number\">Figure 1:</span> b</figcaption></figure>"
+        );
+        assert_eq!(render("[html@tag.strike a]"), "<strike>a</strike>");
+    }
+
+    #[test]
+    fn callouts() {
+        assert_eq!(
+            render("[tip{title = Shortcut} a]"),
+            "<aside class=\"callout callout-tip\" role=\"note\"><p class=\"callout-title\">Shortcut</p>a</aside>"
+        );
+        assert_eq!(
+            render("[callout{kind = nope} a]"),
+            "<aside class=\"callout callout-note\" role=\"note\"><p class=\"callout-title\">Note</p>a</aside>"
+        );
+    }
+
+    #[test]
+    fn shared_index() {
+        let documents = ["[index fox]a", "b[index fox; dog][printindex]"];
+        let mut index = Index::default();
+        for (i, source) in documents.iter().enumerate() {
+            let mut nodes = parse(source.to_string()).unwrap().nodes;
+            index.extend(Index::collect(&mut nodes, &format!("{}.html", i)));
+        }
+        let mut backend = BackendHtml::new("%content%".to_string(), HashMap::new());
+        backend.set_index(Some(index));
+        let html = backend.compile_ast(parse(documents[1].to_string()).unwrap());
+        assert!(html.starts_with("b<span id=\"index-1\" class=\"index-marker\"></span>"));
+        assert!(html.contains("<li>dog <a href=\"1.html#index-1\">1</a></li>"));
+        assert!(html.contains(
+            "<li>fox <a href=\"0.html#index-1\">1</a>, <a href=\"1.html#index-1\">2</a></li>"
+        ));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::serialize::{serialize, OutputMode};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HtmlTag {
+    tag_name: String,
+    attributes: Vec<(String, String)>,
+    child_nodes: Vec<DomNode>,
+}
+
+impl HtmlTag {
+    pub fn new<S: Into<String>>(tag_name: S) -> Self {
+        Self {
+            tag_name: tag_name.into(),
+            attributes: Vec::new(),
+            child_nodes: Vec::new(),
+        }
+    }
+
+    // section: builder methods
+
+    pub fn with_attr<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.set_attr(key, value);
+        self
+    }
+
+    /// Adds the attributes in order, without checking for duplicates
+    pub fn with_attributes<I: IntoIterator<Item = (String, String)>>(mut self, attrs: I) -> Self {
+        self.attributes.extend(attrs);
+        self
+    }
+
+    pub fn with_id<S: Into<String>>(self, id: S) -> Self {
+        self.with_attr("id", id)
+    }
+
+    pub fn with_class(mut self, class: &str) -> Self {
+        self.add_class(class);
+        self
+    }
+
+    pub fn with_child<N: Into<DomNode>>(mut self, node: N) -> Self {
+        self.append_child(node);
+        self
+    }
+
+    pub fn with_children<I: IntoIterator<Item = DomNode>>(mut self, nodes: I) -> Self {
+        self.child_nodes.extend(nodes);
+        self
+    }
+
+    pub fn with_text<S: Into<String>>(self, text: S) -> Self {
+        self.with_child(DomNode::Text(text.into()))
+    }
+
+    // section: accessors
+
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    pub fn set_tag_name<S: Into<String>>(&mut self, tag_name: S) {
+        self.tag_name = tag_name.into();
+    }
+
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut Vec<(String, String)> {
+        &mut self.attributes
+    }
+
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Replaces the value of the attribute if it exists and adds it otherwise
+    pub fn set_attr<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        let key = key.into();
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.attributes.push((key, value)),
+        }
+    }
+
+    pub fn remove_attr(&mut self, key: &str) -> Option<String> {
+        let i = self.attributes.iter().position(|(k, _)| k == key)?;
+        Some(self.attributes.remove(i).1)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.attr("id")
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.attr("class").unwrap_or("").split_whitespace()
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes().any(|c| c == class)
+    }
+
+    pub fn add_class(&mut self, class: &str) {
+        if self.has_class(class) {
+            return;
+        }
+        let classes = match self.attr("class") {
+            Some(s) if !s.trim().is_empty() => format!("{} {}", s, class),
+            _ => class.to_string(),
+        };
+        self.set_attr("class", classes);
+    }
+
+    pub fn child_nodes(&self) -> &[DomNode] {
+        &self.child_nodes
+    }
+
+    pub fn child_nodes_mut(&mut self) -> &mut Vec<DomNode> {
+        &mut self.child_nodes
+    }
+
+    pub fn append_child<N: Into<DomNode>>(&mut self, node: N) {
+        self.child_nodes.push(node.into());
+    }
+}
+
+impl ToString for HtmlTag {
+    fn to_string(&self) -> String {
+        serialize(&[DomNode::Tag(self.clone())], OutputMode::Compact)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DomNode {
+    Tag(HtmlTag),
+    /// Raw html, inserted as-is
+    Text(String),
+    /// A `<!-- comment -->`, `--` in it is broken up so that it can't end the comment
+    Comment(String),
+    /// A sequence of nodes that is serialized as if it were spliced into its parent
+    Fragment(Vec<DomNode>),
+}
+
+impl From<HtmlTag> for DomNode {
+    fn from(t: HtmlTag) -> Self {
+        DomNode::Tag(t)
+    }
+}
+
+impl From<String> for DomNode {
+    fn from(s: String) -> Self {
+        DomNode::Text(s)
+    }
+}
+
+impl From<&str> for DomNode {
+    fn from(s: &str) -> Self {
+        DomNode::Text(s.to_string())
+    }
+}
+
+impl ToString for DomNode {
+    fn to_string(&self) -> String {
+        serialize(std::slice::from_ref(self), OutputMode::Compact)
+    }
+}
+
+impl DomNode {
+    pub fn as_tag(&self) -> Option<&HtmlTag> {
+        match self {
+            DomNode::Tag(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_tag_mut(&mut self) -> Option<&mut HtmlTag> {
+        match self {
+            DomNode::Tag(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn child_nodes(&self) -> &[Self] {
+        match self {
+            DomNode::Tag(t) => &t.child_nodes,
+            DomNode::Fragment(v) => v,
+            DomNode::Text(_) | DomNode::Comment(_) => &[],
+        }
+    }
+
+    pub fn child_nodes_mut(&mut self) -> Option<&mut Vec<Self>> {
+        match self {
+            DomNode::Tag(t) => Some(&mut t.child_nodes),
+            DomNode::Fragment(v) => Some(v),
+            DomNode::Text(_) | DomNode::Comment(_) => None,
+        }
+    }
+
+    /// Calls `f` on this node and all of its descendants, parents before their children
+    pub fn walk<F: FnMut(&DomNode)>(&self, f: &mut F) {
+        f(self);
+        for node in self.child_nodes() {
+            node.walk(&mut *f);
+        }
+    }
+
+    /// Like `walk`, but children are visited after `f` has (possibly) modified their parent
+    pub fn walk_mut<F: FnMut(&mut DomNode)>(&mut self, f: &mut F) {
+        f(self);
+        if let Some(v) = self.child_nodes_mut() {
+            for node in v {
+                node.walk_mut(&mut *f);
+            }
+        }
+    }
+
+    fn find_all_inner<'a, P: FnMut(&HtmlTag) -> bool>(
+        &'a self,
+        pred: &mut P,
+        res: &mut Vec<&'a HtmlTag>,
+    ) {
+        if let DomNode::Tag(t) = self {
+            if pred(t) {
+                res.push(t);
+            }
+        }
+        for node in self.child_nodes() {
+            node.find_all_inner(pred, res);
+        }
+    }
+
+    /// Finds all tags (including this node) that match `pred`, in document order
+    pub fn find_all<P: FnMut(&HtmlTag) -> bool>(&self, mut pred: P) -> Vec<&HtmlTag> {
+        let mut res = Vec::new();
+        self.find_all_inner(&mut pred, &mut res);
+        res
+    }
+
+    pub fn find_by_tag(&self, tag_name: &str) -> Vec<&HtmlTag> {
+        self.find_all(|t| t.tag_name == tag_name)
+    }
+
+    pub fn find_by_class(&self, class: &str) -> Vec<&HtmlTag> {
+        self.find_all(|t| t.has_class(class))
+    }
+
+    pub fn find_by_id(&self, id: &str) -> Option<&HtmlTag> {
+        self.find_all(|t| t.id() == Some(id)).into_iter().next()
+    }
+
+    pub fn find_by_id_mut(&mut self, id: &str) -> Option<&mut HtmlTag> {
+        match self {
+            DomNode::Tag(t) => {
+                if t.id() == Some(id) {
+                    return Some(t);
+                }
+                t.child_nodes
+                    .iter_mut()
+                    .find_map(|node| node.find_by_id_mut(id))
+            }
+            DomNode::Fragment(v) => v.iter_mut().find_map(|node| node.find_by_id_mut(id)),
+            DomNode::Text(_) | DomNode::Comment(_) => None,
+        }
+    }
+
+    /// Calls `f` on every tag in this subtree, parents before their children
+    pub fn for_each_tag_mut<F: FnMut(&mut HtmlTag)>(&mut self, mut f: F) {
+        self.walk_mut(&mut |node| {
+            if let DomNode::Tag(t) = node {
+                f(t)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomNode, HtmlTag};
+
+    fn tree() -> DomNode {
+        HtmlTag::new("div")
+            .with_id("main")
+            .with_child(HtmlTag::new("p").with_class("a").with_text("x"))
+            .with_child(DomNode::Fragment(vec![HtmlTag::new("p")
+                .with_class("a b")
+                .with_id("second")
+                .with_text("y")
+                .into()]))
+            .into()
+    }
+
+    #[test]
+    fn builder() {
+        let mut tag = HtmlTag::new("a")
+            .with_attr("href", "#x")
+            .with_class("one")
+            .with_class("two")
+            .with_class("one");
+        assert_eq!(tag.attr("class"), Some("one two"));
+        tag.set_attr("href", "#y");
+        assert_eq!(
+            tag.attributes(),
+            &[
+                ("href".to_string(), "#y".to_string()),
+                ("class".to_string(), "one two".to_string())
+            ]
+        );
+        assert_eq!(tag.remove_attr("href").as_deref(), Some("#y"));
+        assert_eq!(tag.with_text("t").to_string(), "<a class=\"one two\">t</a>");
+    }
+
+    #[test]
+    fn find() {
+        let mut tree = tree();
+        assert_eq!(tree.find_by_tag("p").len(), 2);
+        assert_eq!(tree.find_by_class("b").len(), 1);
+        assert_eq!(tree.find_by_class("a").len(), 2);
+        assert_eq!(tree.find_by_id("main").map(HtmlTag::tag_name), Some("div"));
+        assert!(tree.find_by_id("none").is_none());
+        tree.find_by_id_mut("second").unwrap().set_tag_name("span");
+        assert_eq!(
+            tree.to_string(),
+            "<div id=\"main\"><p class=\"a\">x</p><span class=\"a b\" id=\"second\">y</span></div>"
+        );
+    }
+
+    #[test]
+    fn walk_mut() {
+        let mut tree = tree();
+        tree.walk_mut(&mut |node| {
+            if let DomNode::Text(s) = node {
+                *s = s.to_uppercase();
+            }
+        });
+        tree.for_each_tag_mut(|t| {
+            t.remove_attr("id");
+        });
+        assert_eq!(
+            tree.to_string(),
+            "<div><p class=\"a\">X</p><p class=\"a b\">Y</p></div>"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let comment = |s: &str| DomNode::Comment(s.to_string()).to_string();
+        assert_eq!(comment(" note "), "<!-- note -->");
+        assert_eq!(comment("a --> b"), "<!--a - -> b-->");
+        assert_eq!(comment("--->"), "<!--- - ->-->");
+        assert_eq!(comment("a-"), "<!--a- -->");
+    }
+}
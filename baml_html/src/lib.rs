@@ -0,0 +1,4 @@
+pub mod backend_html;
+pub mod dom;
+pub mod mathml;
+pub mod serialize;
@@ -1,11 +1,14 @@
-use self::backend_html::BackendHtml;
+use baml_core::index::Index;
+use baml_core::numbering::Numbering;
+use baml_core::shortcodes::Shortcodes;
+use baml_core::stylesheet::StyleSheet;
 use baml_core::{parse, Backend, AST};
+use baml_html::backend_html::BackendHtml;
+use baml_html::serialize::OutputMode;
 use clap::{App, Arg};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-mod backend_html;
-
 fn resolve_path<P: AsRef<Path>>(p: P, mut cwd: PathBuf) -> PathBuf {
     if p.as_ref().is_relative() {
         cwd.push(p);
@@ -34,6 +37,62 @@ fn main() {
                 .default_value("out")
                 .help("The directory to place the final files in"),
         )
+        .arg(
+            Arg::with_name("output-mode")
+                .short("m")
+                .long("output-mode")
+                .takes_value(true)
+                .possible_values(&["compact", "pretty", "minified"])
+                .default_value("compact")
+                .help("How the generated html is formatted"),
+        )
+        .arg(
+            Arg::with_name("indent")
+                .long("indent")
+                .takes_value(true)
+                .default_value("2")
+                .help("The number of spaces per indentation level in pretty output"),
+        )
+        .arg(
+            Arg::with_name("stylesheet")
+                .short("s")
+                .long("stylesheet")
+                .takes_value(true)
+                .help("A file with style rules (`<selector>: <key> = <value>; ...`, one per line) applied to all files"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .help("A file with extra shortcode symbols (`<name> = <symbol>`, one per line) for all files"),
+        )
+        .arg(
+            Arg::with_name("style-block")
+                .long("style-block")
+                .help("emit style rules as a <style> element instead of inline styles"),
+        )
+        .arg(
+            Arg::with_name("autolink")
+                .long("autolink")
+                .help("turn bare urls and email addresses into links"),
+        )
+        .arg(
+            Arg::with_name("smart-typography")
+                .long("smart-typography")
+                .help("use curly quotes, dashes and ellipses, unless a document sets !typography no"),
+        )
+        .arg(
+            Arg::with_name("heading-anchors")
+                .long("heading-anchors")
+                .help("add a link to itself to every heading"),
+        )
+        .arg(
+            Arg::with_name("numbering")
+                .long("numbering")
+                .takes_value(true)
+                .value_name("FORMATS")
+                .help("number headings, with a format (arabic, alpha, Alpha, roman or Roman) per level, e.g. \"arabic alpha\""),
+        )
         .arg(
             Arg::with_name("dry-run")
                 .long("dry-run")
@@ -65,6 +124,65 @@ fn main() {
         HashMap::new(),
     );
 
+    let mut output_mode = matches
+        .value_of("output-mode")
+        .unwrap()
+        .parse::<OutputMode>()
+        .unwrap();
+    if let OutputMode::Pretty(ref mut indent) = output_mode {
+        match matches.value_of("indent").unwrap().parse() {
+            Ok(x) => *indent = x,
+            Err(e) => {
+                eprintln!("error: invalid indentation ({:?})", e);
+                return;
+            }
+        }
+    }
+    backend.set_output_mode(output_mode);
+    backend.set_style_block(matches.is_present("style-block"));
+    backend.set_autolink(matches.is_present("autolink"));
+    backend.set_smart_typography(matches.is_present("smart-typography"));
+    backend.set_heading_anchors(matches.is_present("heading-anchors"));
+    if let Some(formats) = matches.value_of("numbering") {
+        match formats.parse::<Numbering>() {
+            Ok(n) => backend.set_numbering(Some(n)),
+            Err(()) => {
+                eprintln!("error: invalid numbering formats `{}`", formats);
+                return;
+            }
+        }
+    }
+
+    if let Some(path) = matches.value_of_os("stylesheet") {
+        let path = resolve_path(path, cwd.clone());
+        let sheet = match std::fs::read_to_string(&path) {
+            Ok(s) => StyleSheet::parse(&s),
+            Err(e) => Err(format!("can't read {} ({:?})", path.to_string_lossy(), e)),
+        };
+        match sheet {
+            Ok(sheet) => backend.set_stylesheet(sheet),
+            Err(e) => {
+                eprintln!("error: invalid stylesheet: {}", e);
+                return;
+            }
+        }
+    }
+
+    if let Some(path) = matches.value_of_os("symbols") {
+        let path = resolve_path(path, cwd.clone());
+        let shortcodes = match std::fs::read_to_string(&path) {
+            Ok(s) => Shortcodes::parse(&s),
+            Err(e) => Err(format!("can't read {} ({:?})", path.to_string_lossy(), e)),
+        };
+        match shortcodes {
+            Ok(shortcodes) => backend.set_shortcodes(shortcodes),
+            Err(e) => {
+                eprintln!("error: invalid symbols: {}", e);
+                return;
+            }
+        }
+    }
+
     let output_dir = resolve_path(matches.value_of_os("output-dir").unwrap(), cwd.clone());
     if let Err(e) = std::fs::create_dir_all(&output_dir) {
         eprintln!("error: couldn't create output directory ({:?})", e);
@@ -99,6 +217,8 @@ fn main() {
         return;
     }
 
+    // all files are parsed first, so that an index can cover all of them
+    let mut documents = Vec::new();
     for file in matches.values_of_os("FILES").unwrap() {
         let path = resolve_path(file, cwd.clone());
         if path.file_name().is_none() {
@@ -136,6 +256,21 @@ fn main() {
             }
         };
 
+        documents.push((path, out_path, ast));
+    }
+
+    if documents.len() > 1 {
+        let mut index = Index::default();
+        for (_, out_path, ast) in &documents {
+            let page = out_path.file_name().unwrap().to_string_lossy();
+            index.extend(Index::collect(&mut ast.nodes.clone(), &page));
+        }
+        backend.set_index(Some(index));
+    }
+
+    for (path, out_path, ast) in documents {
+        backend.set_document_dir(path.parent().unwrap().to_path_buf());
+
         let compiled = backend.compile_ast(ast);
 
         match std::fs::write(&out_path, compiled) {
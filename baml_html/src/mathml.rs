@@ -0,0 +1,159 @@
+//! Rendering of formulas as MathML
+
+use crate::dom::{DomNode, HtmlTag};
+use baml_core::math::MathNode;
+
+/// Integrals take their scripts to the side even in display math
+const INTEGRALS: &[&str] = &["∫", "∬", "∮"];
+
+/// Escapes text for use as the content of an element
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn leaf(tag_name: &str, s: &str) -> HtmlTag {
+    HtmlTag::new(tag_name).with_text(escape(s))
+}
+
+fn has_limits(node: &MathNode) -> bool {
+    matches!(node, MathNode::LargeOperator(s) if !INTEGRALS.contains(&s.as_str()))
+}
+
+fn render(node: &MathNode) -> HtmlTag {
+    match node {
+        MathNode::Ident(s) => leaf("mi", s),
+        MathNode::Number(s) => leaf("mn", s),
+        MathNode::Operator(s) => leaf("mo", s),
+        MathNode::LargeOperator(s) => {
+            let tag = leaf("mo", s);
+            if INTEGRALS.contains(&s.as_str()) {
+                tag
+            } else {
+                // limits go below and above in display math and to the side inline
+                tag.with_attr("movablelimits", "true")
+            }
+        }
+        MathNode::Text(s) => leaf("mtext", s),
+        MathNode::Space(width) => HtmlTag::new("mspace").with_attr("width", width.as_str()),
+        MathNode::Row(v) => row(v),
+        MathNode::Frac(num, den) => HtmlTag::new("mfrac")
+            .with_child(render(num))
+            .with_child(render(den)),
+        MathNode::Root(x, None) => HtmlTag::new("msqrt").with_child(render(x)),
+        MathNode::Root(x, Some(index)) => HtmlTag::new("mroot")
+            .with_child(render(x))
+            .with_child(render(index)),
+        MathNode::Scripts { base, sub, sup } => {
+            let limits = has_limits(base);
+            let tag_name = match (limits, sub.is_some(), sup.is_some()) {
+                (false, true, true) => "msubsup",
+                (false, true, false) => "msub",
+                (false, false, _) => "msup",
+                (true, true, true) => "munderover",
+                (true, true, false) => "munder",
+                (true, false, _) => "mover",
+            };
+            let mut tag = HtmlTag::new(tag_name).with_child(render(base));
+            for script in sub.iter().chain(sup.iter()) {
+                tag.append_child(render(script));
+            }
+            tag
+        }
+        MathNode::Accent(base, accent) => HtmlTag::new("mover")
+            .with_attr("accent", "true")
+            .with_child(render(base))
+            .with_child(leaf("mo", accent)),
+        MathNode::Fenced(open, content, close) => {
+            let mut tag = HtmlTag::new("mrow");
+            let fence = |s: &str| {
+                leaf("mo", s)
+                    .with_attr("fence", "true")
+                    .with_attr("stretchy", "true")
+            };
+            if !open.is_empty() {
+                tag.append_child(fence(open));
+            }
+            tag.child_nodes_mut()
+                .extend(content.iter().map(|n| DomNode::Tag(render(n))));
+            if !close.is_empty() {
+                tag.append_child(fence(close));
+            }
+            tag
+        }
+        MathNode::Variant(variant, content) => {
+            let mut node = DomNode::Tag(render(content));
+            node.for_each_tag_mut(|t| {
+                if t.tag_name() == "mi" {
+                    t.set_attr("mathvariant", variant.as_str());
+                }
+            });
+            match node {
+                DomNode::Tag(t) => t,
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+fn row(nodes: &[MathNode]) -> HtmlTag {
+    HtmlTag::new("mrow").with_children(nodes.iter().map(|n| DomNode::Tag(render(n))))
+}
+
+/// Renders a formula as a `<math>` element, `display` makes it a block
+pub fn math_element(nodes: &[MathNode], display: bool) -> HtmlTag {
+    let mut tag = HtmlTag::new("math").with_children(nodes.iter().map(|n| DomNode::Tag(render(n))));
+    if display {
+        tag.set_attr("display", "block");
+    }
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::math_element;
+    use crate::serialize::{serialize, OutputMode};
+    use baml_core::math::parse_math;
+
+    fn mathml(s: &str, display: bool) -> String {
+        let tag = math_element(&parse_math(s).unwrap(), display);
+        serialize(&[tag.into()], OutputMode::Compact)
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(
+            mathml("x_i^2", false),
+            "<math><msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup></math>"
+        );
+        assert_eq!(
+            mathml(r"\sum_{k}", false),
+            "<math><munder><mo movablelimits=\"true\">∑</mo><mrow><mi>k</mi></mrow></munder></math>"
+        );
+        assert_eq!(
+            mathml(r"\int^1", false),
+            "<math><msup><mo>∫</mo><mn>1</mn></msup></math>"
+        );
+    }
+
+    #[test]
+    fn fractions_and_roots() {
+        assert_eq!(
+            mathml(r"\frac a{b<c}", false),
+            "<math><mfrac><mi>a</mi><mrow><mi>b</mi><mo>&lt;</mo><mi>c</mi></mrow></mfrac></math>"
+        );
+        assert_eq!(
+            mathml(r"\sqrt\[3\]x", false),
+            "<math><mroot><mi>x</mi><mrow><mn>3</mn></mrow></mroot></math>"
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            mathml("x", true),
+            "<math display=\"block\"><mi>x</mi></math>"
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use crate::dom::{DomNode, HtmlTag};
+use std::str::FromStr;
+
+/// Elements that are laid out as blocks, so whitespace around them is not rendered
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Elements whose content is rendered with all of its whitespace
+const PREFORMATTED_ELEMENTS: &[&str] = &["pre", "textarea", "script", "style"];
+
+/// Elements that can't have content and therefore have no closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum OutputMode {
+    /// Every node directly after the previous one, without any added whitespace
+    #[default]
+    Compact,
+    /// Block elements on their own lines, indented by the given amount of spaces per level
+    Pretty(usize),
+    /// All whitespace that doesn't affect the rendered page is removed
+    Minified,
+}
+
+impl FromStr for OutputMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "compact" => OutputMode::Compact,
+            "pretty" => OutputMode::Pretty(2),
+            "minified" => OutputMode::Minified,
+            _ => return Err(()),
+        })
+    }
+}
+
+fn is_block(node: &&DomNode) -> bool {
+    match node {
+        DomNode::Tag(t) => BLOCK_ELEMENTS.contains(&t.tag_name()),
+        _ => false,
+    }
+}
+
+fn is_whitespace(node: &&DomNode) -> bool {
+    match node {
+        DomNode::Text(s) => s.chars().all(char::is_whitespace),
+        _ => false,
+    }
+}
+
+/// Splices the content of fragments into the surrounding list
+fn flatten(nodes: &[DomNode]) -> Vec<&DomNode> {
+    let mut res = Vec::new();
+    for node in nodes {
+        match node {
+            DomNode::Fragment(v) => res.append(&mut flatten(v)),
+            node => res.push(node),
+        }
+    }
+    res
+}
+
+fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// Makes text safe to be the content of a comment, by putting a space between consecutive hyphens
+/// (and around the ends where needed), so that it can't close the comment early
+fn escape_comment(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '-' && res.ends_with('-') {
+            res.push(' ');
+        }
+        res.push(c);
+    }
+    if res.starts_with('>') || res.starts_with("->") {
+        res.insert(0, ' ');
+    }
+    if res.ends_with('-') {
+        res.push(' ');
+    }
+    res
+}
+
+/// Replaces every run of whitespace with a single space
+fn collapse_whitespace(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut last_ws = false;
+    for c in s.chars() {
+        if c.is_whitespace() {
+            if !last_ws {
+                res.push(' ');
+            }
+            last_ws = true;
+        } else {
+            res.push(c);
+            last_ws = false;
+        }
+    }
+    res
+}
+
+struct Serializer {
+    mode: OutputMode,
+    out: String,
+}
+
+impl Serializer {
+    fn newline(&mut self, depth: usize, indent: usize) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out.push_str(&" ".repeat(depth * indent));
+    }
+
+    fn open_tag(&mut self, tag: &HtmlTag) {
+        self.out.push('<');
+        self.out.push_str(tag.tag_name());
+        for (k, v) in tag.attributes() {
+            self.out.push_str(&format!(" {}=\"{}\"", k, escape_attr(v)));
+        }
+        if VOID_ELEMENTS.contains(&tag.tag_name()) {
+            self.out.push_str(" />");
+        } else {
+            self.out.push('>');
+        }
+    }
+
+    fn close_tag(&mut self, tag: &HtmlTag) {
+        if !VOID_ELEMENTS.contains(&tag.tag_name()) {
+            self.out.push_str(&format!("</{}>", tag.tag_name()));
+        }
+    }
+
+    /// Writes the nodes without adding any whitespace
+    fn write_inline(&mut self, nodes: &[&DomNode], pre: bool) {
+        for node in nodes {
+            match node {
+                DomNode::Text(s) if self.mode == OutputMode::Minified && !pre => {
+                    self.out.push_str(&collapse_whitespace(s))
+                }
+                DomNode::Text(s) => self.out.push_str(s),
+                DomNode::Comment(s) => self.out.push_str(&format!("<!--{}-->", escape_comment(s))),
+                DomNode::Fragment(v) => self.write_inline(&flatten(v), pre),
+                DomNode::Tag(t) => {
+                    let pre = pre || PREFORMATTED_ELEMENTS.contains(&t.tag_name());
+                    self.open_tag(t);
+                    if self.mode == OutputMode::Minified && !pre && is_block(node) {
+                        self.write_minified(&flatten(t.child_nodes()));
+                    } else {
+                        self.write_inline(&flatten(t.child_nodes()), pre);
+                    }
+                    self.close_tag(t);
+                }
+            }
+        }
+    }
+
+    /// Writes nodes that are in a block context, dropping whitespace next to block elements
+    fn write_minified(&mut self, nodes: &[&DomNode]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if is_whitespace(node)
+                && (i == 0
+                    || i + 1 == nodes.len()
+                    || is_block(&nodes[i - 1])
+                    || is_block(&nodes[i + 1]))
+            {
+                continue;
+            }
+            self.write_inline(std::slice::from_ref(node), false);
+        }
+    }
+
+    /// Writes nodes that are in a block context, putting block elements on their own lines
+    fn write_pretty(&mut self, nodes: &[&DomNode], depth: usize, indent: usize) {
+        let mut after_block = false;
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                DomNode::Tag(t) if is_block(node) => {
+                    let children = flatten(t.child_nodes());
+                    self.newline(depth, indent);
+                    self.open_tag(t);
+                    if PREFORMATTED_ELEMENTS.contains(&t.tag_name()) {
+                        self.write_inline(&children, true);
+                    } else if children.iter().any(is_block) {
+                        self.write_pretty(&children, depth + 1, indent);
+                        self.newline(depth, indent);
+                    } else {
+                        self.write_inline(&children, false);
+                    }
+                    self.close_tag(t);
+                    after_block = true;
+                }
+                _ => {
+                    // whitespace next to a block element isn't rendered anyway
+                    if is_whitespace(node) && (after_block || nodes.get(i + 1).is_none_or(is_block))
+                    {
+                        continue;
+                    }
+                    if after_block || i == 0 {
+                        self.newline(depth, indent);
+                        after_block = false;
+                    }
+                    self.write_inline(std::slice::from_ref(node), false);
+                }
+            }
+        }
+    }
+}
+
+/// Turns the nodes into html, formatted according to `mode`
+pub fn serialize(nodes: &[DomNode], mode: OutputMode) -> String {
+    let mut s = Serializer {
+        mode,
+        out: String::new(),
+    };
+    let nodes = flatten(nodes);
+    match mode {
+        OutputMode::Compact => s.write_inline(&nodes, false),
+        OutputMode::Pretty(indent) => s.write_pretty(&nodes, 0, indent),
+        OutputMode::Minified => s.write_minified(&nodes),
+    }
+    s.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serialize, OutputMode};
+    use crate::dom::{DomNode, HtmlTag};
+
+    fn document() -> Vec<DomNode> {
+        vec![HtmlTag::new("div")
+            .with_child(
+                HtmlTag::new("p")
+                    .with_text("a ")
+                    .with_child(HtmlTag::new("b").with_text("b")),
+            )
+            .with_text("\n  ")
+            .with_child(HtmlTag::new("pre").with_text("  x\n    y"))
+            .with_child(HtmlTag::new("img").with_attr("src", "a\"b.png"))
+            .into()]
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!(
+            serialize(&document(), OutputMode::Compact),
+            "<div><p>a <b>b</b></p>\n  <pre>  x\n    y</pre><img src=\"a&quot;b.png\" /></div>"
+        );
+    }
+
+    #[test]
+    fn pretty() {
+        assert_eq!(
+            serialize(&document(), OutputMode::Pretty(2)),
+            "<div>\n  <p>a <b>b</b></p>\n  <pre>  x\n    y</pre>\n  <img src=\"a&quot;b.png\" />\n</div>"
+        );
+    }
+
+    #[test]
+    fn pretty_keeps_inline_whitespace() {
+        let nodes = vec![
+            DomNode::from("a"),
+            HtmlTag::new("b").with_text("b").into(),
+            DomNode::from(" c"),
+        ];
+        assert_eq!(serialize(&nodes, OutputMode::Pretty(4)), "a<b>b</b> c");
+    }
+
+    #[test]
+    fn minified() {
+        assert_eq!(
+            serialize(&document(), OutputMode::Minified),
+            "<div><p>a <b>b</b></p><pre>  x\n    y</pre><img src=\"a&quot;b.png\" /></div>"
+        );
+        let nodes = vec![DomNode::from("a \n\t b"), HtmlTag::new("br").into()];
+        assert_eq!(serialize(&nodes, OutputMode::Minified), "a b<br />");
+    }
+
+    #[test]
+    fn fragments_are_spliced() {
+        let nodes = vec![
+            HtmlTag::new("p").with_text("a").into(),
+            DomNode::Fragment(vec![
+                DomNode::from(" "),
+                HtmlTag::new("p").with_text("b").into(),
+            ]),
+        ];
+        assert_eq!(serialize(&nodes, OutputMode::Minified), "<p>a</p><p>b</p>");
+        assert_eq!(
+            serialize(&nodes, OutputMode::Pretty(2)),
+            "<p>a</p>\n<p>b</p>"
+        );
+    }
+}